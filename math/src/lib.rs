@@ -16,6 +16,8 @@ impl Location {
         }
     }
 
+    /// Rotates, then translates, then scales, so the scale stretches the position as well. Use
+    /// `model_matrix` to place an object.
     pub fn calculate_matrix(&self) -> glam::Mat4 {
         Mat4::from_scale(self.scale)
             * Mat4::from_translation(self.position)
@@ -27,6 +29,11 @@ impl Location {
             )
     }
 
+    /// Scales, then rotates, then translates.
+    pub fn model_matrix(&self) -> glam::Mat4 {
+        self.translation_matrix() * self.rotation_matrix() * Mat4::from_scale(self.scale)
+    }
+
     pub fn translation_matrix(&self) -> Mat4 {
         Mat4::from_translation(self.position)
     }
@@ -59,6 +66,53 @@ impl Location {
     }
 }
 
+/// Axis aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Returns `None` if there are no points.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Self::new(first, first), |aabb, p| {
+            Self::new(aabb.min.min(p), aabb.max.max(p))
+        }))
+    }
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// The box around all eight corners after transforming them by `matrix`.
+    pub fn transformed(&self, matrix: Mat4) -> Self {
+        let corners = (0..8).map(|i| {
+            matrix.transform_point3(Vec3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            ))
+        });
+        Self::from_points(corners).unwrap()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Radius of the sphere through all corners.
+    pub fn radius(&self) -> f32 {
+        (self.max - self.min).length() * 0.5
+    }
+}
+
 #[inline]
 pub fn deg_2_rad(degrees: f32) -> f32 {
     degrees * std::f32::consts::PI / 180.0
@@ -69,9 +123,13 @@ pub fn rad_2_deg(rad: f32) -> f32 {
     rad * 180.0 / std::f32::consts::PI
 }
 
+/// Distance from the camera to the near and far plane of `perspective_rh`.
+pub const NEAR: f32 = 0.1;
+pub const FAR: f32 = 1000.0;
+
 #[inline]
 pub fn perspective_rh(aspect_ratio: f32) -> [[f32; 4]; 4] {
-    glam::Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, aspect_ratio, 0.1, 1000.0)
+    glam::Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, aspect_ratio, NEAR, FAR)
         .to_cols_array_2d()
 }
 
@@ -101,4 +159,61 @@ mod tests {
 
         assert_eq!(translation_from_matrix(matrix), location.position);
     }
+
+    #[test]
+    fn calculate_matrix_test() {
+        let location = Location::new(
+            [0.0, deg_2_rad(90.0), 0.0],
+            [1.0, 2.0, 3.0],
+            [2.0, 1.0, 1.0],
+        );
+        let point = location
+            .calculate_matrix()
+            .transform_point3(Vec3::new(1.0, 0.0, 0.0));
+
+        // Turned onto -z, moved, then stretched along x.
+        assert!(point.abs_diff_eq(Vec3::new(2.0, 2.0, 2.0), 1e-6));
+    }
+
+    #[test]
+    fn model_matrix_test() {
+        let location = Location::new(
+            [0.0, deg_2_rad(90.0), 0.0],
+            [1.0, 2.0, 3.0],
+            [2.0, 1.0, 1.0],
+        );
+        let point = location
+            .model_matrix()
+            .transform_point3(Vec3::new(1.0, 0.0, 0.0));
+
+        // Stretched along x, turned onto -z, then moved.
+        assert!(point.abs_diff_eq(Vec3::new(1.0, 2.0, 1.0), 1e-6));
+    }
+
+    #[test]
+    fn aabb_from_points_test() {
+        let aabb = Aabb::from_points([
+            Vec3::new(1.0, -2.0, 3.0),
+            Vec3::new(-1.0, 4.0, 0.0),
+            Vec3::new(0.0, 0.0, 5.0),
+        ])
+        .unwrap();
+
+        assert_eq!(aabb.min, Vec3::new(-1.0, -2.0, 0.0));
+        assert_eq!(aabb.max, Vec3::new(1.0, 4.0, 5.0));
+        assert_eq!(Aabb::from_points([]), None);
+    }
+
+    #[test]
+    fn aabb_transformed_test() {
+        let aabb = Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0));
+        let matrix =
+            Mat4::from_translation(Vec3::new(0.0, 0.0, -10.0)) * Mat4::from_scale(Vec3::splat(2.0));
+        let transformed = aabb.transformed(matrix);
+
+        assert_eq!(transformed.min, Vec3::new(-2.0, -2.0, -12.0));
+        assert_eq!(transformed.max, Vec3::new(2.0, 2.0, -8.0));
+        assert_eq!(transformed.center(), Vec3::new(0.0, 0.0, -10.0));
+        assert_eq!(transformed.radius(), 12f32.sqrt());
+    }
 }
@@ -0,0 +1,243 @@
+//! Keyframe animation of instances.
+//!
+//! An instance can have a track for its position, rotation, scale and colour. Each track is a list
+//! of `(seconds, value)` keys sorted by time, and whatever a track does not cover keeps the static
+//! value from the instance. Rotations are in degrees like everywhere else in the scene file.
+
+use math::Vec3;
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Animation {
+    /// Starts over after the last key of the longest track. Otherwise every track holds its last
+    /// value.
+    #[serde(default = "yes")]
+    pub looping: bool,
+    #[serde(default)]
+    pub position: Option<Track>,
+    #[serde(default)]
+    pub rotation: Option<Track>,
+    #[serde(default)]
+    pub scale: Option<Track>,
+    #[serde(default)]
+    pub colour: Option<Track>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Jumps to the next value once its key is reached.
+    Step,
+    /// Catmull-Rom spline through the keys. The tangents are taken per second, so that unevenly
+    /// spaced keys don't change speed with a jolt.
+    Cubic,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Track {
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    pub keys: Vec<(f32, [f32; 3])>,
+}
+
+fn yes() -> bool {
+    true
+}
+
+impl Animation {
+    pub fn tracks(&self) -> impl Iterator<Item = &Track> {
+        [&self.position, &self.rotation, &self.scale, &self.colour]
+            .into_iter()
+            .flatten()
+    }
+
+    /// Maps the time since the animation started to the time on its tracks.
+    pub fn local_time(&self, time: f32) -> f32 {
+        let duration = self
+            .tracks()
+            .filter_map(|track| track.keys.last())
+            .map(|(time, _)| *time)
+            .fold(0.0, f32::max);
+        if self.looping && duration > 0.0 {
+            time.rem_euclid(duration)
+        } else {
+            time
+        }
+    }
+}
+
+impl Track {
+    /// Returns what is wrong with the keys, if anything.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.keys.is_empty() {
+            return Err("a track has no keys");
+        }
+        if self.keys.iter().any(|(time, _)| !time.is_finite()) {
+            return Err("a key time is not a number");
+        }
+        if self.keys.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+            return Err("the keys of a track are not sorted by time");
+        }
+        Ok(())
+    }
+
+    /// The value at `time`. Before the first and after the last key the track holds still.
+    pub fn sample(&self, time: f32) -> Vec3 {
+        let keys = &self.keys;
+        let next = keys.partition_point(|(key_time, _)| *key_time <= time);
+        if next == 0 {
+            return keys[0].1.into();
+        }
+        if next == keys.len() {
+            return keys[next - 1].1.into();
+        }
+
+        let (start, from) = keys[next - 1];
+        let (end, to) = keys[next];
+        let t = (time - start) / (end - start);
+        match self.interpolation {
+            Interpolation::Step => from.into(),
+            Interpolation::Linear => Vec3::from(from).lerp(to.into(), t),
+            Interpolation::Cubic => {
+                // The first and last key stand in for the missing neighbours at the ends.
+                let before = keys[next.saturating_sub(2)];
+                let after = keys[(next + 1).min(keys.len() - 1)];
+                let per_second = |(start, from): (f32, [f32; 3]), (end, to): (f32, [f32; 3])| {
+                    (Vec3::from(to) - Vec3::from(from)) / (end - start)
+                };
+                let duration = end - start;
+                hermite(
+                    from.into(),
+                    per_second(before, keys[next]) * duration,
+                    to.into(),
+                    per_second(keys[next - 1], after) * duration,
+                    t,
+                )
+            }
+        }
+    }
+}
+
+/// The cubic from `p0` to `p1` that leaves with the tangent `m0` and arrives with `m1`, both over
+/// the whole of `t` from 0 to 1.
+fn hermite(p0: Vec3, m0: Vec3, p1: Vec3, m1: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2.0 * t3 - 3.0 * t2 + 1.0) * p0
+        + (t3 - 2.0 * t2 + t) * m0
+        + (3.0 * t2 - 2.0 * t3) * p1
+        + (t3 - t2) * m1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Interpolation; 3] = [
+        Interpolation::Step,
+        Interpolation::Linear,
+        Interpolation::Cubic,
+    ];
+
+    fn track(interpolation: Interpolation, keys: &[(f32, f32)]) -> Track {
+        Track {
+            interpolation,
+            keys: keys
+                .iter()
+                .map(|&(time, x)| (time, [x, 0.0, 0.0]))
+                .collect(),
+        }
+    }
+
+    fn assert_x(value: Vec3, x: f32) {
+        assert!(
+            value.abs_diff_eq(Vec3::new(x, 0.0, 0.0), 1e-5),
+            "{:?} != {}",
+            value,
+            x
+        );
+    }
+
+    #[test]
+    fn outside_keys_test() {
+        for interpolation in ALL {
+            let track = track(interpolation, &[(1.0, 2.0), (2.0, 4.0), (3.0, 8.0)]);
+            assert_x(track.sample(0.0), 2.0);
+            assert_x(track.sample(-1.0), 2.0);
+            assert_x(track.sample(3.5), 8.0);
+            assert_x(track.sample(100.0), 8.0);
+        }
+    }
+
+    #[test]
+    fn exact_keys_test() {
+        let keys = [(0.0, 1.0), (0.5, -1.0), (2.0, 3.0), (2.5, 0.0)];
+        for interpolation in ALL {
+            let track = track(interpolation, &keys);
+            for (time, x) in keys {
+                assert_x(track.sample(time), x);
+            }
+        }
+    }
+
+    #[test]
+    fn single_key_test() {
+        for interpolation in ALL {
+            let track = track(interpolation, &[(1.0, 5.0)]);
+            assert_x(track.sample(0.0), 5.0);
+            assert_x(track.sample(1.0), 5.0);
+            assert_x(track.sample(2.0), 5.0);
+        }
+    }
+
+    #[test]
+    fn step_test() {
+        let track = track(Interpolation::Step, &[(0.0, 1.0), (1.0, 2.0)]);
+        assert_x(track.sample(0.5), 1.0);
+        assert_x(track.sample(0.999), 1.0);
+    }
+
+    #[test]
+    fn linear_test() {
+        let track = track(Interpolation::Linear, &[(0.0, 1.0), (2.0, 3.0), (3.0, 0.0)]);
+        assert_x(track.sample(0.5), 1.5);
+        assert_x(track.sample(2.5), 1.5);
+    }
+
+    #[test]
+    fn cubic_test() {
+        // Evenly moving keys move evenly in between too, however far apart they are.
+        let straight = track(Interpolation::Cubic, &[(0.0, 0.0), (1.0, 1.0), (3.0, 3.0)]);
+        assert_x(straight.sample(0.5), 0.5);
+        assert_x(straight.sample(2.0), 2.0);
+
+        // Goes through 1 at 1 second at the speed from the key before to the key after, 0.5 per
+        // second, on both sides.
+        let curve = track(Interpolation::Cubic, &[(0.0, 0.0), (1.0, 1.0), (4.0, 2.0)]);
+        let step = 0.001;
+        let speed_before = (curve.sample(1.0) - curve.sample(1.0 - step)) / step;
+        let speed_after = (curve.sample(1.0 + step) - curve.sample(1.0)) / step;
+        assert!((speed_before.x - 0.5).abs() < 0.01, "{}", speed_before.x);
+        assert!((speed_after.x - 0.5).abs() < 0.01, "{}", speed_after.x);
+    }
+
+    #[test]
+    fn looping_test() {
+        let mut animation = Animation {
+            looping: true,
+            position: Some(track(Interpolation::Linear, &[(0.0, 0.0), (1.0, 1.0)])),
+            rotation: None,
+            scale: None,
+            colour: Some(track(Interpolation::Linear, &[(0.5, 0.0), (2.0, 1.0)])),
+        };
+        // The longest track sets the duration.
+        assert_eq!(animation.local_time(0.5), 0.5);
+        assert_eq!(animation.local_time(2.5), 0.5);
+        assert_eq!(animation.local_time(4.0), 0.0);
+        assert_eq!(animation.local_time(-0.5), 1.5);
+
+        animation.looping = false;
+        assert_eq!(animation.local_time(2.5), 2.5);
+    }
+}
@@ -0,0 +1,276 @@
+//! Finding the files the demo reads.
+//!
+//! Relative paths are looked up in the asset root (`--assets <dir>`, the working directory by
+//! default), then next to the executable and finally among the assets compiled into the binary.
+//! The built-in ones are the default scene with the monkey, the textures it draws and its sky, so
+//! the demo starts from any directory. Absolute paths are only looked up where they point.
+
+use std::borrow::Cow;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Paths relative to the asset root and their contents.
+const EMBEDDED: [(&str, &[u8]); 5] = [
+    (
+        "scenes/default.ron",
+        include_bytes!("../scenes/default.ron"),
+    ),
+    ("monke.obj", include_bytes!("../monke.obj")),
+    (
+        "textures/checker.png",
+        include_bytes!("../textures/checker.png"),
+    ),
+    (
+        "textures/tiles_normal.png",
+        include_bytes!("../textures/tiles_normal.png"),
+    ),
+    (
+        "environments/sky.hdr",
+        include_bytes!("../environments/sky.hdr"),
+    ),
+];
+
+pub struct Assets {
+    roots: Vec<PathBuf>,
+}
+
+/// Where an asset was found.
+#[derive(Clone, Debug)]
+pub enum Asset {
+    File(PathBuf),
+    /// Compiled into the binary, with its path relative to the asset root.
+    Embedded(&'static str, &'static [u8]),
+}
+
+/// The asset could not be found anywhere.
+#[derive(Debug)]
+pub struct AssetError {
+    pub path: PathBuf,
+    /// Every file that was looked for, in order.
+    pub tried: Vec<PathBuf>,
+    pub tried_embedded: bool,
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "could not find {}, tried:", self.path.display())?;
+        for path in &self.tried {
+            write!(f, "\n  {}", path.display())?;
+        }
+        if self.tried_embedded {
+            write!(f, "\n  the assets built into the executable")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+impl Assets {
+    /// Searches `root` if given, the working directory otherwise, and then the directory of the
+    /// executable.
+    pub fn new(root: Option<PathBuf>) -> Self {
+        let root = root
+            .or_else(|| env::current_dir().ok())
+            .unwrap_or_else(|| PathBuf::from("."));
+        let executable_dir = env::current_exe()
+            .ok()
+            .and_then(|path| path.parent().map(Path::to_path_buf));
+        let mut roots = vec![root];
+        roots.extend(executable_dir.filter(|dir| !roots.contains(dir)));
+        Self { roots }
+    }
+
+    pub fn resolve(&self, path: &Path) -> Result<Asset, AssetError> {
+        if path.is_absolute() {
+            return match path.is_file() {
+                true => Ok(Asset::File(path.to_path_buf())),
+                false => Err(AssetError {
+                    path: path.to_path_buf(),
+                    tried: vec![path.to_path_buf()],
+                    tried_embedded: false,
+                }),
+            };
+        }
+
+        let path = normalize(path);
+        let mut tried = vec![];
+        for root in &self.roots {
+            let candidate = root.join(&path);
+            if candidate.is_file() {
+                return Ok(Asset::File(candidate));
+            }
+            tried.push(candidate);
+        }
+        let key = path
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        match EMBEDDED.iter().find(|(name, _)| *name == key) {
+            Some((name, contents)) => Ok(Asset::Embedded(name, contents)),
+            None => Err(AssetError {
+                path,
+                tried,
+                tried_embedded: true,
+            }),
+        }
+    }
+}
+
+impl Asset {
+    /// The file, or for a built-in asset its path relative to the asset root.
+    pub fn path(&self) -> &Path {
+        match self {
+            Asset::File(path) => path,
+            Asset::Embedded(name, _) => Path::new(name),
+        }
+    }
+
+    /// Only assets on disk can change.
+    pub fn file(&self) -> Option<&Path> {
+        match self {
+            Asset::File(path) => Some(path),
+            Asset::Embedded(..) => None,
+        }
+    }
+
+    pub fn read(&self) -> io::Result<Cow<'static, [u8]>> {
+        match self {
+            Asset::File(path) => fs::read(path).map(Cow::Owned),
+            Asset::Embedded(_, contents) => Ok(Cow::Borrowed(contents)),
+        }
+    }
+
+    pub fn read_to_string(&self) -> io::Result<String> {
+        String::from_utf8(self.read()?.into_owned())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl fmt::Display for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Asset::File(path) => write!(f, "{}", path.display()),
+            Asset::Embedded(name, _) => write!(f, "built-in {}", name),
+        }
+    }
+}
+
+/// Resolves `.` and `..` without touching the disk, so that `scenes/../monke.obj` finds the
+/// built-in `monke.obj`. A `..` that leaves the root is kept.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two empty asset roots in a fresh temporary directory.
+    fn roots(name: &str) -> (PathBuf, Assets) {
+        let dir = env::temp_dir().join(format!("rendering_demo_{}_{}", name, std::process::id()));
+        let roots = vec![dir.join("first"), dir.join("second")];
+        for root in &roots {
+            fs::create_dir_all(root).unwrap();
+        }
+        (dir, Assets { roots })
+    }
+
+    #[test]
+    fn normalize_test() {
+        assert_eq!(normalize(Path::new("a/./b")), Path::new("a/b"));
+        assert_eq!(normalize(Path::new("a/b/../c")), Path::new("a/c"));
+        assert_eq!(
+            normalize(Path::new("scenes/../monke.obj")),
+            Path::new("monke.obj")
+        );
+        assert_eq!(normalize(Path::new("../a")), Path::new("../a"));
+        assert_eq!(normalize(Path::new("a/../../b")), Path::new("../b"));
+        assert_eq!(normalize(Path::new("./.")), Path::new(""));
+    }
+
+    #[test]
+    fn root_order_test() {
+        let (dir, assets) = roots("root_order");
+        let [first, second] = [&assets.roots[0], &assets.roots[1]];
+        fs::write(second.join("mesh.obj"), "second").unwrap();
+        let found = assets.resolve(Path::new("mesh.obj")).unwrap();
+        assert_eq!(found.file(), Some(second.join("mesh.obj").as_path()));
+
+        fs::write(first.join("mesh.obj"), "first").unwrap();
+        let found = assets.resolve(Path::new("./mesh.obj")).unwrap();
+        assert_eq!(found.read_to_string().unwrap(), "first");
+
+        // A file on disk wins over the built-in one.
+        fs::write(second.join("monke.obj"), "on disk").unwrap();
+        let found = assets.resolve(Path::new("monke.obj")).unwrap();
+        assert_eq!(found.file(), Some(second.join("monke.obj").as_path()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn embedded_test() {
+        let (dir, assets) = roots("embedded");
+        let found = assets.resolve(Path::new("scenes/../monke.obj")).unwrap();
+        assert!(matches!(found, Asset::Embedded("monke.obj", _)));
+        assert_eq!(found.file(), None);
+        assert_eq!(&*found.read().unwrap(), include_bytes!("../monke.obj"));
+
+        let found = assets.resolve(Path::new("textures/checker.png")).unwrap();
+        assert_eq!(found.path(), Path::new("textures/checker.png"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn not_found_test() {
+        let (dir, assets) = roots("not_found");
+        let error = assets
+            .resolve(Path::new("textures/missing.png"))
+            .unwrap_err();
+        assert_eq!(
+            error.tried,
+            [
+                assets.roots[0].join("textures/missing.png"),
+                assets.roots[1].join("textures/missing.png"),
+            ]
+        );
+        assert!(error.tried_embedded);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "could not find textures/missing.png, tried:\n  {}\n  {}\n  the assets built into the executable",
+                error.tried[0].display(),
+                error.tried[1].display(),
+            )
+        );
+
+        let absolute = dir.join("missing.png");
+        let error = assets.resolve(&absolute).unwrap_err();
+        assert_eq!(error.tried, [absolute.as_path()]);
+        assert!(!error.tried_embedded);
+        assert_eq!(
+            error.to_string(),
+            format!("could not find {0}, tried:\n  {0}", absolute.display())
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+use math::{deg_2_rad, Aabb, Location, Mat4, Vec3};
+use std::collections::HashSet;
+use winit::event::{ElementState, MouseButton, VirtualKeyCode};
+
+/// Radians of rotation per unit of mouse motion.
+const LOOK_SENSITIVITY: f32 = 0.003;
+/// Fraction of the orbit distance panned per unit of mouse motion.
+const PAN_SENSITIVITY: f32 = 0.0015;
+const SPRINT_MULTIPLIER: f32 = 4.0;
+const MIN_SPEED: f32 = 0.1;
+const MAX_SPEED: f32 = 500.0;
+const MIN_DISTANCE: f32 = 0.01;
+/// Vertical field of view of `math::perspective_rh`.
+const FOV_Y: f32 = std::f32::consts::FRAC_PI_2;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CameraMode {
+    /// WASD and mouse look.
+    FreeFly,
+    /// Turntable around `Camera::target`. Left drag orbits, middle drag pans, scroll zooms.
+    Orbit,
+}
+
+/// `location.position` is the inverse of the eye position, which is what
+/// `Location::ez_camera_matrix` expects. Both modes share the same yaw and pitch, so switching
+/// between them keeps the view where it is.
+pub struct Camera {
+    pub location: Location,
+    pub mode: CameraMode,
+    /// Free-fly speed in units per second.
+    pub speed: f32,
+    /// Point the orbit mode circles around.
+    pub target: Vec3,
+    /// Distance between the eye and `target`.
+    pub distance: f32,
+    held: HashSet<VirtualKeyCode>,
+    buttons: HashSet<MouseButton>,
+}
+
+impl Camera {
+    /// `yaw` and `pitch` are in degrees.
+    pub fn new(eye: [f32; 3], yaw: f32, pitch: f32) -> Self {
+        let mut camera = Self {
+            location: Location::new(
+                [deg_2_rad(pitch), deg_2_rad(yaw), 0.0],
+                (-Vec3::from(eye)).into(),
+                [1.0; 3],
+            ),
+            mode: CameraMode::FreeFly,
+            speed: 5.0,
+            target: Vec3::ZERO,
+            distance: 2.0,
+            held: HashSet::new(),
+            buttons: HashSet::new(),
+        };
+        camera.target = camera.eye() + camera.forward() * camera.distance;
+        camera
+    }
+
+    pub fn eye(&self) -> Vec3 {
+        -self.location.position
+    }
+
+    /// Direction the camera looks in, in world space.
+    pub fn forward(&self) -> Vec3 {
+        let Vec3 {
+            x: pitch, y: yaw, ..
+        } = self.location.rotation;
+        Vec3::new(
+            pitch.cos() * yaw.sin(),
+            -pitch.sin(),
+            -pitch.cos() * yaw.cos(),
+        )
+    }
+
+    fn right(&self) -> Vec3 {
+        let yaw = self.location.rotation.y;
+        Vec3::new(yaw.cos(), 0.0, yaw.sin())
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            CameraMode::FreeFly => {
+                self.target = self.eye() + self.forward() * self.distance;
+                CameraMode::Orbit
+            }
+            CameraMode::Orbit => CameraMode::FreeFly,
+        };
+    }
+
+    pub fn key(&mut self, key: VirtualKeyCode, state: ElementState) {
+        match state {
+            ElementState::Pressed => self.held.insert(key),
+            ElementState::Released => self.held.remove(&key),
+        };
+    }
+
+    pub fn mouse_button(&mut self, button: MouseButton, state: ElementState) {
+        match state {
+            ElementState::Pressed => self.buttons.insert(button),
+            ElementState::Released => self.buttons.remove(&button),
+        };
+    }
+
+    /// Looks around in free-fly mode, drags in orbit mode.
+    pub fn mouse_motion(&mut self, (dx, dy): (f64, f64)) {
+        let (dx, dy) = (dx as f32, dy as f32);
+        match self.mode {
+            CameraMode::FreeFly => self.rotate(dx, dy),
+            CameraMode::Orbit if self.buttons.contains(&MouseButton::Left) => {
+                self.rotate(dx, dy);
+                self.place_on_orbit();
+            }
+            CameraMode::Orbit if self.buttons.contains(&MouseButton::Middle) => {
+                let up = self.right().cross(self.forward());
+                let scale = PAN_SENSITIVITY * self.distance;
+                self.target += (up * dy - self.right() * dx) * scale;
+                self.place_on_orbit();
+            }
+            CameraMode::Orbit => {}
+        }
+    }
+
+    fn rotate(&mut self, dx: f32, dy: f32) {
+        let limit = deg_2_rad(89.0);
+        self.location.rotation.y += dx * LOOK_SENSITIVITY;
+        self.location.rotation.x =
+            (self.location.rotation.x + dy * LOOK_SENSITIVITY).clamp(-limit, limit);
+    }
+
+    /// Every line scrolled up makes the free-fly camera 10% faster, or zooms the orbit in by 10%.
+    pub fn scroll(&mut self, lines: f32) {
+        match self.mode {
+            CameraMode::FreeFly => {
+                self.speed = (self.speed * 1.1f32.powf(lines)).clamp(MIN_SPEED, MAX_SPEED)
+            }
+            CameraMode::Orbit => {
+                self.distance = (self.distance * 0.9f32.powf(lines)).max(MIN_DISTANCE);
+                self.place_on_orbit();
+            }
+        }
+    }
+
+    /// Moves the camera so that all of `bounds` is in view without changing where it looks.
+    pub fn frame(&mut self, bounds: Aabb, aspect_ratio: f32) {
+        let fov_x = 2.0 * ((FOV_Y / 2.0).tan() * aspect_ratio).atan();
+        let half_fov = FOV_Y.min(fov_x) / 2.0;
+
+        self.target = bounds.center();
+        self.distance = (bounds.radius() / half_fov.sin()).max(MIN_DISTANCE);
+        self.place_on_orbit();
+    }
+
+    fn place_on_orbit(&mut self) {
+        self.location.position = -(self.target - self.forward() * self.distance);
+    }
+
+    /// Moves the free-fly camera by however far it travels in `delta` seconds with the held keys.
+    pub fn update(&mut self, delta: f32) {
+        if self.mode != CameraMode::FreeFly {
+            return;
+        }
+
+        let mut distance = self.speed * delta;
+        if self.held.contains(&VirtualKeyCode::LShift) {
+            distance *= SPRINT_MULTIPLIER;
+        }
+
+        let axis = |positive, negative| {
+            self.held.contains(&positive) as i32 as f32
+                - self.held.contains(&negative) as i32 as f32
+        };
+        let forward = axis(VirtualKeyCode::W, VirtualKeyCode::S);
+        let right = axis(VirtualKeyCode::D, VirtualKeyCode::A);
+        let up = axis(VirtualKeyCode::Space, VirtualKeyCode::LControl);
+
+        self.location.move_from_look(forward * distance);
+        self.location.straffe_from_look(right * distance);
+        self.location.position.y -= up * distance;
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        // Vulkan's clip space points y down.
+        Mat4::from_scale(Vec3::new(1.0, -1.0, 1.0)) * self.location.ez_camera_matrix()
+    }
+}
@@ -0,0 +1,486 @@
+//! Image based lighting. An equirectangular HDR image of the surroundings is turned into the cube
+//! maps the shaders sample: the sky itself, the irradiance that lights the diffuse colour and the
+//! reflections prefiltered for every roughness. The BRDF lookup table that goes with them only
+//! depends on the angle to the camera and the roughness, so it is the same for every environment.
+//!
+//! The maps are computed the first time a level that draws them comes up, and only again when the
+//! image or its intensity changes, see `Renderer::prepare`. The lookup table is computed once for
+//! the whole run. Faces are in the order +x, -x, +y, -y, +z, -z, and laid out the way Vulkan
+//! samples them.
+
+use crate::assets::Asset;
+
+use math::{Vec2, Vec3};
+use std::f32::consts::PI;
+use std::sync::OnceLock;
+use std::thread;
+
+/// Mip levels of the prefiltered map, for roughness 0, 0.25, 0.5, 0.75 and 1. `shader.frag` spreads
+/// the roughness over however many levels the map has.
+pub const PREFILTERED_LEVELS: u32 = 5;
+/// Width and height of the top level of the prefiltered map.
+const PREFILTERED_SIZE: u32 = 128;
+/// Irradiance changes slowly over the directions, a few texels are enough.
+const IRRADIANCE_SIZE: u32 = 32;
+/// The irradiance is summed over every texel of the sky at this size.
+const IRRADIANCE_SOURCE_SIZE: u32 = 16;
+/// The sky faces are a quarter of the width of the image, up to this size.
+const MAX_SKY_SIZE: u32 = 512;
+/// Width and height of the BRDF lookup table.
+pub const BRDF_LUT_SIZE: u32 = 64;
+/// Directions looked at for every texel of the prefiltered map.
+const PREFILTER_SAMPLES: u32 = 64;
+/// Directions looked at for every texel of the BRDF lookup table.
+const BRDF_SAMPLES: u32 = 256;
+
+/// Linear radiance, row by row from the top. The middle column looks down -z, the top row is
+/// straight up.
+pub struct Equirect {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3>,
+}
+
+/// Every mip level from the largest, each with all six faces row by row.
+pub struct Cubemap {
+    pub size: u32,
+    pub levels: Vec<Vec<Vec3>>,
+}
+
+pub struct EnvironmentMaps {
+    /// Drawn behind the scene.
+    pub sky: Cubemap,
+    /// The cosine weighted average of the light arriving from around each direction.
+    pub irradiance: Cubemap,
+    /// Reflections, blurrier with every level, see `PREFILTERED_LEVELS`.
+    pub prefiltered: Cubemap,
+}
+
+impl Equirect {
+    /// Decodes a Radiance HDR file, or any other format `image` can read.
+    pub fn load(asset: &Asset) -> Result<Self, image::ImageError> {
+        let contents = asset.read().map_err(image::ImageError::IoError)?;
+        let image = image::load_from_memory(&contents)?.into_rgb32f();
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.pixels().map(|pixel| Vec3::from(pixel.0)).collect(),
+        })
+    }
+
+    /// Blends the four pixels around `direction`, wrapping around the sides.
+    fn sample(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.normalize();
+        let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let pixel = |x: f32, y: f32| {
+            let x = (x as i64).rem_euclid(self.width as i64) as u32;
+            let y = (y as u32).min(self.height - 1);
+            self.pixels[(y * self.width + x) as usize]
+        };
+        let top = pixel(x0, y0).lerp(pixel(x0 + 1.0, y0), fx);
+        let bottom = pixel(x0, y0 + 1.0).lerp(pixel(x0 + 1.0, y0 + 1.0), fx);
+        top.lerp(bottom, fy)
+    }
+}
+
+impl Cubemap {
+    /// One level of `size` by `size` faces, with `radiance` called at the middle of every texel.
+    /// Every face is filled in on a thread of its own.
+    fn level(size: u32, radiance: impl Fn(Vec3) -> Vec3 + Sync) -> Vec<Vec3> {
+        let radiance = &radiance;
+        thread::scope(|scope| {
+            let faces: Vec<_> = (0..6)
+                .map(|face| {
+                    scope.spawn(move || {
+                        (0..size)
+                            .flat_map(|row| (0..size).map(move |column| (row, column)))
+                            .map(|(row, column)| {
+                                let s = (column as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                                let t = (row as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                                radiance(face_direction(face, s, t).normalize())
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            faces
+                .into_iter()
+                .flat_map(|face| face.join().unwrap())
+                .collect()
+        })
+    }
+
+    /// Adds the smaller levels down to 1 by 1, each texel the average of the four above it.
+    fn with_mipmaps(size: u32, top: Vec<Vec3>) -> Self {
+        let mut levels = vec![top];
+        let mut level_size = size;
+        while level_size > 1 {
+            let above = levels.last().unwrap();
+            let half = level_size / 2;
+            let texel = |face: u32, row: u32, column: u32| {
+                above[((face * level_size + row) * level_size + column) as usize]
+            };
+            let level = (0..6)
+                .flat_map(|face| {
+                    (0..half).flat_map(move |row| (0..half).map(move |column| (face, row, column)))
+                })
+                .map(|(face, row, column)| {
+                    (texel(face, row * 2, column * 2)
+                        + texel(face, row * 2, column * 2 + 1)
+                        + texel(face, row * 2 + 1, column * 2)
+                        + texel(face, row * 2 + 1, column * 2 + 1))
+                        / 4.0
+                })
+                .collect();
+            levels.push(level);
+            level_size = half;
+        }
+        Self { size, levels }
+    }
+
+    /// Lights nothing, for scenes without an environment.
+    pub fn black() -> Self {
+        Self {
+            size: 1,
+            levels: vec![vec![Vec3::ZERO; 6]],
+        }
+    }
+
+    fn level_size(&self, level: usize) -> u32 {
+        (self.size >> level).max(1)
+    }
+
+    /// Blends the four texels around `direction` on `level`. Unlike the GPU it doesn't blend across
+    /// the edges of the faces.
+    fn sample(&self, level: usize, direction: Vec3) -> Vec3 {
+        let size = self.level_size(level);
+        let texels = &self.levels[level];
+        let (face, uv) = face_coordinates(direction);
+        let position = (uv * size as f32 - 0.5).clamp(Vec2::ZERO, Vec2::splat((size - 1) as f32));
+        let (x0, y0) = (position.x.floor() as u32, position.y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(size - 1), (y0 + 1).min(size - 1));
+        let (fx, fy) = (position.x.fract(), position.y.fract());
+        let texel = |x: u32, y: u32| texels[((face * size + y) * size + x) as usize];
+        let top = texel(x0, y0).lerp(texel(x1, y0), fx);
+        let bottom = texel(x0, y1).lerp(texel(x1, y1), fx);
+        top.lerp(bottom, fy)
+    }
+}
+
+impl EnvironmentMaps {
+    /// Projects `equirect` onto a cube and convolves it. `intensity` scales all of it.
+    pub fn new(equirect: &Equirect, intensity: f32) -> Self {
+        let sky_size = (equirect.width / 4)
+            .next_power_of_two()
+            .clamp(PREFILTERED_SIZE, MAX_SKY_SIZE);
+        let sky = Cubemap::with_mipmaps(
+            sky_size,
+            Cubemap::level(sky_size, |direction| equirect.sample(direction) * intensity),
+        );
+        let irradiance = irradiance(&sky);
+        let prefiltered = prefiltered(&sky);
+        Self {
+            sky,
+            irradiance,
+            prefiltered,
+        }
+    }
+}
+
+/// Where the middle of a face texel points, with `s` and `t` from -1 to 1 across and down the face.
+fn face_direction(face: u32, s: f32, t: f32) -> Vec3 {
+    match face {
+        0 => Vec3::new(1.0, -t, -s),
+        1 => Vec3::new(-1.0, -t, s),
+        2 => Vec3::new(s, 1.0, t),
+        3 => Vec3::new(s, -1.0, -t),
+        4 => Vec3::new(s, -t, 1.0),
+        _ => Vec3::new(-s, -t, -1.0),
+    }
+}
+
+/// The face `direction` points at and where on it, from 0 to 1 across and down.
+fn face_coordinates(direction: Vec3) -> (u32, Vec2) {
+    let abs = direction.abs();
+    let (face, s, t, major) = if abs.x >= abs.y && abs.x >= abs.z {
+        match direction.x > 0.0 {
+            true => (0, -direction.z, -direction.y, abs.x),
+            false => (1, direction.z, -direction.y, abs.x),
+        }
+    } else if abs.y >= abs.z {
+        match direction.y > 0.0 {
+            true => (2, direction.x, direction.z, abs.y),
+            false => (3, direction.x, -direction.z, abs.y),
+        }
+    } else {
+        match direction.z > 0.0 {
+            true => (4, direction.x, -direction.y, abs.z),
+            false => (5, -direction.x, -direction.y, abs.z),
+        }
+    };
+    (face, (Vec2::new(s, t) / major + 1.0) * 0.5)
+}
+
+/// Sums the light of every texel of a small level of `sky`, weighted by the solid angle it covers
+/// and how squarely it shines on the surface. Divided by pi, so that a white surface under an
+/// evenly lit sky comes out as bright as the sky.
+fn irradiance(sky: &Cubemap) -> Cubemap {
+    let source_level = (sky.size / IRRADIANCE_SOURCE_SIZE).trailing_zeros() as usize;
+    let size = sky.level_size(source_level);
+    // Texels near the corners of a face are further away and seen at an angle, so they cover less.
+    let texel_area = (2.0 / size as f32).powi(2);
+    let sources: Vec<(Vec3, Vec3)> = (0..6)
+        .flat_map(|face| {
+            (0..size).flat_map(move |row| (0..size).map(move |column| (face, row, column)))
+        })
+        .map(|(face, row, column)| {
+            let s = (column as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+            let t = (row as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+            let solid_angle = texel_area / (1.0 + s * s + t * t).powf(1.5);
+            let index = ((face * size + row) * size + column) as usize;
+            let light = sky.levels[source_level][index] * solid_angle;
+            (face_direction(face, s, t).normalize(), light)
+        })
+        .collect();
+    let level = Cubemap::level(IRRADIANCE_SIZE, |normal| {
+        sources.iter().fold(Vec3::ZERO, |sum, &(direction, light)| {
+            sum + light * normal.dot(direction).max(0.0)
+        }) / PI
+    });
+    Cubemap {
+        size: IRRADIANCE_SIZE,
+        levels: vec![level],
+    }
+}
+
+/// Averages the reflections around every direction the way a GGX highlight of the level's
+/// roughness spreads them, looking straight at the surface. Each sample is taken from a smaller
+/// level of `sky` the less likely its direction is, so that the bright spots don't turn into
+/// noise.
+fn prefiltered(sky: &Cubemap) -> Cubemap {
+    let first_level = (sky.size / PREFILTERED_SIZE).trailing_zeros() as usize;
+    let texel_solid_angle = 4.0 * PI / (6.0 * (PREFILTERED_SIZE as f32).powi(2));
+    let levels = (0..PREFILTERED_LEVELS)
+        .map(|level| {
+            let size = PREFILTERED_SIZE >> level;
+            let roughness = level as f32 / (PREFILTERED_LEVELS - 1) as f32;
+            if level == 0 {
+                return Cubemap::level(size, |direction| sky.sample(first_level, direction));
+            }
+
+            // The halfway vectors and levels don't depend on the direction, only their frame does.
+            let samples: Vec<(Vec3, usize)> = (0..PREFILTER_SAMPLES)
+                .map(|i| {
+                    let halfway = ggx_halfway(hammersley(i, PREFILTER_SAMPLES), roughness);
+                    let n_dot_h = halfway.z;
+                    // Looking straight at the surface the chance of a direction is D / 4.
+                    let pdf = distribution(n_dot_h, roughness) / 4.0;
+                    let sample_solid_angle = 1.0 / (PREFILTER_SAMPLES as f32 * pdf + 0.0001);
+                    let blur = 0.5 * (sample_solid_angle / texel_solid_angle).log2() + 1.0;
+                    let level = first_level + blur.max(0.0).round() as usize;
+                    (halfway, level.min(sky.levels.len() - 1))
+                })
+                .collect();
+            Cubemap::level(size, |normal| {
+                let (tangent, bitangent) = normal.any_orthonormal_pair();
+                let mut sum = Vec3::ZERO;
+                let mut weight = 0.0;
+                for &(halfway, level) in &samples {
+                    let halfway = tangent * halfway.x + bitangent * halfway.y + normal * halfway.z;
+                    let to_light = 2.0 * normal.dot(halfway) * halfway - normal;
+                    let n_dot_l = normal.dot(to_light);
+                    if n_dot_l > 0.0 {
+                        sum += sky.sample(level, to_light) * n_dot_l;
+                        weight += n_dot_l;
+                    }
+                }
+                sum / weight.max(0.0001)
+            })
+        })
+        .collect();
+    Cubemap {
+        size: PREFILTERED_SIZE,
+        levels,
+    }
+}
+
+/// The scale and bias the highlights put on the reflectance head on, with the cosine of the angle
+/// between the normal and the camera across and the roughness down. Computed on the first call.
+pub fn brdf_lut() -> &'static [[f32; 2]] {
+    static LUT: OnceLock<Vec<[f32; 2]>> = OnceLock::new();
+    LUT.get_or_init(|| {
+        let size = BRDF_LUT_SIZE;
+        (0..size)
+            .flat_map(|row| (0..size).map(move |column| (row, column)))
+            .map(|(row, column)| {
+                let n_dot_v = (column as f32 + 0.5) / size as f32;
+                let roughness = (row as f32 + 0.5) / size as f32;
+                let to_camera = Vec3::new((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+                // Smith's method with the Schlick-GGX approximation, with the k for image based light.
+                let k = roughness * roughness / 2.0;
+                let g1 = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+                let (mut scale, mut bias) = (0.0, 0.0);
+                for i in 0..BRDF_SAMPLES {
+                    let halfway = ggx_halfway(hammersley(i, BRDF_SAMPLES), roughness);
+                    let v_dot_h = to_camera.dot(halfway);
+                    let to_light = 2.0 * v_dot_h * halfway - to_camera;
+                    let (n_dot_l, n_dot_h) = (to_light.z, halfway.z);
+                    if n_dot_l > 0.0 {
+                        let visibility =
+                            g1(n_dot_v) * g1(n_dot_l) * v_dot_h.max(0.0) / (n_dot_h * n_dot_v);
+                        let fresnel = (1.0 - v_dot_h.max(0.0)).powi(5);
+                        scale += (1.0 - fresnel) * visibility;
+                        bias += fresnel * visibility;
+                    }
+                }
+                [scale, bias].map(|value| value / BRDF_SAMPLES as f32)
+            })
+            .collect()
+    })
+}
+
+/// Points spread evenly over the unit square, the `i`th of `count`.
+fn hammersley(i: u32, count: u32) -> Vec2 {
+    Vec2::new(
+        i as f32 / count as f32,
+        i.reverse_bits() as f32 / 2f32.powi(32),
+    )
+}
+
+/// A halfway vector around +z, more of them where the GGX distribution of `roughness` has more
+/// microfacets.
+fn ggx_halfway(point: Vec2, roughness: f32) -> Vec3 {
+    let alpha = roughness * roughness;
+    let phi = 2.0 * PI * point.x;
+    let cos_theta = ((1.0 - point.y) / (1.0 + (alpha * alpha - 1.0) * point.y)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+}
+
+/// The same as in `shader.frag`.
+fn distribution(n_dot_h: f32, roughness: f32) -> f32 {
+    let alpha = roughness * roughness;
+    let alpha_squared = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha_squared - 1.0) + 1.0;
+    alpha_squared / (PI * d * d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant_sky(size: u32, colour: Vec3) -> Cubemap {
+        Cubemap::with_mipmaps(size, vec![colour; (6 * size * size) as usize])
+    }
+
+    #[test]
+    fn face_round_trip_test() {
+        let steps = [-1.0, -0.75, -0.5, 0.0, 0.5, 0.75, 1.0];
+        for face in 0..6 {
+            for s in steps {
+                for t in steps {
+                    let direction = face_direction(face, s, t);
+                    let (found, uv) = face_coordinates(direction);
+                    let back = face_direction(found, uv.x * 2.0 - 1.0, uv.y * 2.0 - 1.0);
+                    assert!(
+                        back.normalize().abs_diff_eq(direction.normalize(), 1e-6),
+                        "face {} at ({}, {}) came back as {} at {}",
+                        face,
+                        s,
+                        t,
+                        found,
+                        uv
+                    );
+                    // Edges and corners belong to more than one face, anywhere else has to come
+                    // back where it started.
+                    if s.abs() < 1.0 && t.abs() < 1.0 {
+                        assert_eq!(found, face);
+                        assert!(uv.abs_diff_eq(Vec2::new(s + 1.0, t + 1.0) * 0.5, 1e-6));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn equirect_to_cube_test() {
+        // Red sky above the horizon, blue ground below it, and a white spot straight ahead.
+        let (width, height) = (16, 8);
+        let pixels = (0..height)
+            .flat_map(|row| (0..width).map(move |column| (row, column)))
+            .map(|(row, column)| match (row, column) {
+                (3 | 4, 7 | 8) => Vec3::ONE,
+                (0..=3, _) => Vec3::X,
+                _ => Vec3::Z,
+            })
+            .collect();
+        let equirect = Equirect {
+            width,
+            height,
+            pixels,
+        };
+        // Three by three, so that the middle texel looks straight out of the face.
+        let sky = Cubemap::level(3, |direction| equirect.sample(direction));
+        let texel = |face: usize, row: usize| sky[face * 9 + row * 3 + 1];
+
+        assert!(texel(2, 1).abs_diff_eq(Vec3::X, 1e-6));
+        assert!(texel(3, 1).abs_diff_eq(Vec3::Z, 1e-6));
+        assert!(texel(5, 1).abs_diff_eq(Vec3::ONE, 1e-6));
+        // Behind and to the sides the top row is above the horizon and the bottom one below it.
+        for face in [0, 1, 4] {
+            assert!(texel(face, 0).abs_diff_eq(Vec3::X, 1e-6), "{}", face);
+            assert!(texel(face, 2).abs_diff_eq(Vec3::Z, 1e-6), "{}", face);
+        }
+    }
+
+    #[test]
+    fn constant_irradiance_test() {
+        let colour = Vec3::new(0.25, 0.5, 2.0);
+        let irradiance = irradiance(&constant_sky(PREFILTERED_SIZE, colour));
+
+        assert_eq!(irradiance.size, IRRADIANCE_SIZE);
+        for texel in &irradiance.levels[0] {
+            assert!(
+                texel.abs_diff_eq(colour, colour.max_element() * 0.01),
+                "{}",
+                texel
+            );
+        }
+    }
+
+    #[test]
+    fn constant_prefiltered_test() {
+        let colour = Vec3::new(0.25, 0.5, 2.0);
+        let prefiltered = prefiltered(&constant_sky(PREFILTERED_SIZE, colour));
+
+        assert_eq!(prefiltered.levels.len(), PREFILTERED_LEVELS as usize);
+        for (level, texels) in prefiltered.levels.iter().enumerate() {
+            assert_eq!(
+                texels.len(),
+                (6 * prefiltered.level_size(level).pow(2)) as usize
+            );
+            for texel in texels {
+                assert!(texel.abs_diff_eq(colour, 1e-5), "{}: {}", level, texel);
+            }
+        }
+    }
+
+    #[test]
+    fn brdf_lut_test() {
+        let lut = brdf_lut();
+        assert_eq!(lut.len(), (BRDF_LUT_SIZE * BRDF_LUT_SIZE) as usize);
+
+        // Head on and perfectly smooth, everything is reflected as it is.
+        let [scale, bias] = lut[(BRDF_LUT_SIZE - 1) as usize];
+        assert!((scale - 1.0).abs() < 0.02, "{}", scale);
+        assert!(bias.abs() < 0.02, "{}", bias);
+        for &[scale, bias] in lut {
+            assert!(scale >= 0.0 && bias >= 0.0 && scale + bias <= 1.0 + 1e-3);
+        }
+    }
+}
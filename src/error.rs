@@ -0,0 +1,119 @@
+//! Everything that can stop the demo, with messages meant for whoever is running it.
+
+use crate::scene::SceneError;
+use crate::shaders::runtime::ShaderError;
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use vulkano::{
+    buffer::cpu_access::ReadLockError,
+    command_buffer::{
+        BuildError, CommandBufferBeginError, CommandBufferExecError, CopyError,
+        PipelineExecutionError, RenderPassError,
+    },
+    descriptor_set::DescriptorSetCreationError,
+    device::{physical::PhysicalDeviceError, DeviceCreationError},
+    image::{immutable::ImmutableImageCreationError, view::ImageViewCreationError, ImageError},
+    instance::InstanceCreationError,
+    memory::allocator::AllocationCreationError,
+    pipeline::{compute::ComputePipelineCreationError, graphics::GraphicsPipelineCreationError},
+    render_pass::{FramebufferCreationError, RenderPassCreationError},
+    sampler::SamplerCreationError,
+    shader::ShaderCreationError,
+    swapchain::{AcquireError, SwapchainCreationError},
+    sync::FlushError,
+    LoadingError, VulkanError,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Library(LoadingError),
+    Instance(InstanceCreationError),
+    Window(vulkano_win::CreationError),
+    Devices(VulkanError),
+    /// What was needed and every device that was found, with what it is missing.
+    NoDevice(&'static str, Vec<(String, String)>),
+    Device(DeviceCreationError),
+    Surface(PhysicalDeviceError),
+    FormatProperties(PhysicalDeviceError),
+    Swapchain(SwapchainCreationError),
+    Acquire(AcquireError),
+    RenderPass(RenderPassCreationError),
+    Image(ImageError),
+    ImageView(ImageViewCreationError),
+    Upload(ImmutableImageCreationError),
+    Framebuffer(FramebufferCreationError),
+    Buffer(AllocationCreationError),
+    Readback(ReadLockError),
+    CommandBuffer(CommandBufferBeginError),
+    Copy(CopyError),
+    DescriptorSet(DescriptorSetCreationError),
+    /// Beginning or ending a render pass.
+    RenderPassCommand(RenderPassError),
+    Draw(PipelineExecutionError),
+    Dispatch(PipelineExecutionError),
+    BuildCommandBuffer(BuildError),
+    Execute(CommandBufferExecError),
+    Flush(FlushError),
+    Sampler(SamplerCreationError),
+    Pipeline(GraphicsPipelineCreationError),
+    ComputePipeline(ComputePipelineCreationError),
+    BuiltInShader(ShaderCreationError),
+    Shader(ShaderError),
+    Scene(SceneError),
+    Output(PathBuf, io::Error),
+    Png(PathBuf, png::EncodingError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Library(e) => write!(f, "failed to load the Vulkan library: {}", e),
+            Error::Instance(e) => write!(f, "failed to create a Vulkan instance: {}", e),
+            Error::Window(e) => write!(f, "failed to open a window: {}", e),
+            Error::Devices(e) => write!(f, "failed to list the Vulkan devices: {}", e),
+            Error::NoDevice(needed, found) if found.is_empty() => {
+                write!(f, "no Vulkan device {}, none were found at all", needed)
+            }
+            Error::NoDevice(needed, found) => {
+                write!(f, "no Vulkan device {}; found:", needed)?;
+                for (name, problem) in found {
+                    write!(f, "\n  {}: {}", name, problem)?;
+                }
+                Ok(())
+            }
+            Error::Device(e) => write!(f, "failed to create the Vulkan device: {}", e),
+            Error::Surface(e) => write!(f, "failed to query the window surface: {}", e),
+            Error::FormatProperties(e) => write!(f, "failed to query an image format: {}", e),
+            Error::Swapchain(e) => write!(f, "failed to create the swapchain: {}", e),
+            Error::Acquire(e) => write!(f, "failed to acquire the next swapchain image: {}", e),
+            Error::RenderPass(e) => write!(f, "failed to create a render pass: {}", e),
+            Error::Image(e) => write!(f, "failed to create an image: {}", e),
+            Error::ImageView(e) => write!(f, "failed to create an image view: {}", e),
+            Error::Upload(e) => write!(f, "failed to upload an image: {}", e),
+            Error::Framebuffer(e) => write!(f, "failed to create a framebuffer: {}", e),
+            Error::Buffer(e) => write!(f, "failed to allocate a buffer: {}", e),
+            Error::Readback(e) => write!(f, "failed to read back the image: {}", e),
+            Error::CommandBuffer(e) => write!(f, "failed to start a command buffer: {}", e),
+            Error::Copy(e) => write!(f, "failed to record a copy: {}", e),
+            Error::DescriptorSet(e) => write!(f, "failed to create a descriptor set: {}", e),
+            Error::RenderPassCommand(e) => write!(f, "failed to record a render pass: {}", e),
+            Error::Draw(e) => write!(f, "failed to record a draw: {}", e),
+            Error::Dispatch(e) => write!(f, "failed to record a compute dispatch: {}", e),
+            Error::BuildCommandBuffer(e) => write!(f, "failed to build a command buffer: {}", e),
+            Error::Execute(e) => write!(f, "failed to submit a command buffer: {}", e),
+            Error::Flush(e) => write!(f, "failed to run a command buffer: {}", e),
+            Error::Sampler(e) => write!(f, "failed to create a sampler: {}", e),
+            Error::Pipeline(e) => write!(f, "failed to build a pipeline: {}", e),
+            Error::ComputePipeline(e) => write!(f, "failed to build a compute pipeline: {}", e),
+            Error::BuiltInShader(e) => write!(f, "failed to load the built-in shaders: {}", e),
+            Error::Shader(e) => write!(f, "{}", e),
+            Error::Scene(e) => write!(f, "{}", e),
+            Error::Output(path, e) => write!(f, "failed to write {}: {}", path.display(), e),
+            Error::Png(path, e) => write!(f, "failed to write {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,159 @@
+use crate::assets::Assets;
+use crate::camera::Camera;
+use crate::error::Error;
+use crate::render::{pick_device, Renderer};
+use crate::scene::Scene;
+use crate::settings::{Args, DrawMode, Levels};
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, CopyImageToBufferInfo},
+    device::{Device, DeviceCreateInfo, DeviceExtensions, Features, QueueCreateInfo},
+    format::Format,
+    image::{AttachmentImage, ImageUsage},
+    instance::{Instance, InstanceCreateInfo},
+    sync::{now, GpuFuture},
+    VulkanLibrary,
+};
+
+/// Format of the offscreen target. Matches the byte layout of an RGBA PNG, and like a PNG holds
+/// sRGB encoded colour, the same as the swapchain of the window.
+const FORMAT: Format = Format::R8G8B8A8_SRGB;
+
+/// Renders every requested level into an offscreen image and writes each one to
+/// `<output>/level_<n>.png`. Needs no window, surface or swapchain, so it works on a software
+/// Vulkan driver without a display.
+pub fn render_levels(args: &Args, assets: &Assets, scene: &Scene) -> Result<(), Error> {
+    let library = VulkanLibrary::new().map_err(Error::Library)?;
+    let instance = Instance::new(
+        library,
+        InstanceCreateInfo {
+            // Enable enumerating devices that use non-conformant vulkan implementations. (ex. MoltenVK)
+            enumerate_portability: true,
+            ..Default::default()
+        },
+    )
+    .map_err(Error::Instance)?;
+
+    let features = Features {
+        fill_mode_non_solid: true,
+        ..Features::empty()
+    };
+    let (physical_device, queue_family_index) =
+        pick_device(&instance, &DeviceExtensions::empty(), &features, None)?;
+
+    println!(
+        "Using device: {} (type: {:?})",
+        physical_device.properties().device_name,
+        physical_device.properties().device_type,
+    );
+
+    let (device, mut queues) = Device::new(
+        physical_device,
+        DeviceCreateInfo {
+            queue_create_infos: vec![QueueCreateInfo {
+                queue_family_index,
+                ..Default::default()
+            }],
+            enabled_features: features,
+            ..Default::default()
+        },
+    )
+    .map_err(Error::Device)?;
+
+    let queue = queues.next().unwrap();
+
+    let mut renderer = Renderer::new(device.clone(), queue.clone(), FORMAT, scene, assets)?;
+
+    let camera = Camera::new(scene.camera.eye, scene.camera.yaw, scene.camera.pitch);
+
+    let [width, height] = args.size;
+    let image = AttachmentImage::with_usage(
+        &*renderer.memory_allocator,
+        args.size,
+        FORMAT,
+        ImageUsage {
+            transfer_src: true,
+            ..ImageUsage::empty()
+        },
+    )
+    .map_err(Error::Image)?;
+
+    let readback = CpuAccessibleBuffer::from_iter(
+        &renderer.memory_allocator,
+        BufferUsage {
+            transfer_dst: true,
+            ..BufferUsage::empty()
+        },
+        false,
+        (0..width * height * 4).map(|_| 0u8),
+    )
+    .map_err(Error::Buffer)?;
+
+    fs::create_dir_all(&args.output).map_err(|e| Error::Output(args.output.clone(), e))?;
+
+    for &level in &args.levels {
+        renderer.prepare(level)?;
+        let (pipelines, targets) = renderer.window_size_dependent_setup(
+            std::slice::from_ref(&image),
+            level,
+            DrawMode::Solid,
+        )?;
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &renderer.command_buffer_allocator,
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(Error::CommandBuffer)?;
+        renderer.draw(
+            &mut builder,
+            targets[0].clone(),
+            &pipelines,
+            level,
+            camera.view_matrix(),
+            0.0,
+        )?;
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                image.clone(),
+                readback.clone(),
+            ))
+            .map_err(Error::Copy)?;
+        let command_buffer = builder.build().map_err(Error::BuildCommandBuffer)?;
+
+        now(device.clone())
+            .then_execute(queue.clone(), command_buffer)
+            .map_err(Error::Execute)?
+            .then_signal_fence_and_flush()
+            .map_err(Error::Flush)?
+            .wait(None)
+            .map_err(Error::Flush)?;
+
+        let path = args.output.join(level_file_name(level));
+        write_png(&path, args.size, &readback.read().map_err(Error::Readback)?)
+            .map_err(|e| Error::Png(path.clone(), e))?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn level_file_name(level: Levels) -> String {
+    format!("level_{}.png", level as u32)
+}
+
+fn write_png(
+    path: &Path,
+    [width, height]: [u32; 2],
+    rgba: &[u8],
+) -> Result<(), png::EncodingError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)
+}
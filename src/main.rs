@@ -1,66 +1,79 @@
+mod animation;
+mod assets;
+mod camera;
+mod environment;
+mod error;
+mod headless;
+mod mesh;
+mod render;
+mod scene;
 mod settings;
 mod shaders;
-
-use crate::shaders::{fs, vs, MonkeInstance, Vertex};
-
-use math::{perspective_rh, Mat3, Mat4, Vec3};
-use obj::{load_obj, Obj};
-use std::fs::File;
-use std::io::BufReader;
-use std::{sync::Arc, time::Instant};
+mod watch;
+
+use crate::assets::Assets;
+use crate::camera::{Camera, CameraMode};
+use crate::error::Error;
+use crate::render::{pick_device, pick_surface_format, Renderer};
+use crate::scene::Scene;
+use crate::shaders::runtime;
+use crate::watch::FileWatcher;
+use math::Vec3;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
 use vulkano::device::Features;
-use vulkano::image::SampleCount;
 
-use crate::settings::Levels;
-use vulkano::pipeline::graphics::multisample::MultisampleState;
+use crate::settings::{Args, DrawMode, Levels};
 use vulkano::sync::now;
 use vulkano::{
-    buffer::{BufferUsage, CpuAccessibleBuffer, CpuBufferPool, TypedBufferAccess},
-    command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
-        RenderPassBeginInfo, SubpassContents,
-    },
-    descriptor_set::{
-        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
-    },
-    device::{
-        physical::PhysicalDeviceType, Device, DeviceCreateInfo, DeviceExtensions, DeviceOwned,
-        QueueCreateInfo,
-    },
-    format::Format,
-    image::{view::ImageView, AttachmentImage, ImageAccess, ImageUsage, SwapchainImage},
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage},
+    device::{Device, DeviceCreateInfo, DeviceExtensions, QueueCreateInfo},
+    format::NumericType,
+    image::ImageUsage,
     instance::{Instance, InstanceCreateInfo},
-    memory::allocator::{MemoryUsage, StandardMemoryAllocator},
-    pipeline::{
-        graphics::{
-            depth_stencil::DepthStencilState,
-            input_assembly::InputAssemblyState,
-            vertex_input::BuffersDefinition,
-            viewport::{Viewport, ViewportState},
-        },
-        GraphicsPipeline, Pipeline, PipelineBindPoint,
-    },
-    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
-    shader::ShaderModule,
+    pipeline::GraphicsPipeline,
     swapchain::{
-        acquire_next_image, AcquireError, Swapchain, SwapchainCreateInfo, SwapchainCreationError,
-        SwapchainPresentInfo,
+        acquire_next_image, AcquireError, Surface, Swapchain, SwapchainCreateInfo,
+        SwapchainCreationError, SwapchainPresentInfo,
     },
     sync::{FlushError, GpuFuture},
     VulkanLibrary,
 };
 use vulkano_win::VkSurfaceBuild;
-use winit::event::VirtualKeyCode;
+use winit::event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode};
 use winit::event_loop::ControlFlow;
-use winit::window::Fullscreen;
+use winit::window::CursorGrabMode;
 use winit::{
     event::{Event, WindowEvent},
     event_loop::EventLoop,
     window::{Window, WindowBuilder},
 };
 
+const TITLE: &str = "RENDERING DEMO";
+/// How far a press of the arrow keys or Page Up/Down moves the selected light.
+const LIGHT_STEP: f32 = 0.25;
+/// How many stops a press of `[` or `]` changes the exposure by.
+const EXPOSURE_STEP: f32 = 0.5;
+
 fn main() {
-    let library = VulkanLibrary::new().unwrap();
+    let args = Args::parse();
+    let assets = Assets::new(args.assets.clone());
+    let scene = Scene::load(&assets, &args.scene).unwrap_or_else(|e| exit_with(e));
+    let result = match args.headless {
+        true if args.material_grid => {
+            headless::render_levels(&args, &assets, &scene.material_grid())
+        }
+        true => headless::render_levels(&args, &assets, &scene),
+        false => run(args, assets, scene),
+    };
+    result.unwrap_or_else(|e| exit_with(e));
+}
+
+/// Opens the window and draws into it until it is closed. Only returns if starting up fails.
+fn run(args: Args, assets: Assets, mut scene: Scene) -> Result<(), Error> {
+    let library = VulkanLibrary::new().map_err(Error::Library)?;
     let required_extensions = vulkano_win::required_extensions(&library);
     let instance = Instance::new(
         library,
@@ -71,41 +84,25 @@ fn main() {
             ..Default::default()
         },
     )
-    .unwrap();
+    .map_err(Error::Instance)?;
 
     let event_loop = EventLoop::new();
     let surface = WindowBuilder::new()
-        .with_title("RENDERING DEMO")
+        .with_title(TITLE)
         .with_maximized(true)
         .build_vk_surface(&event_loop, instance.clone())
-        .unwrap();
+        .map_err(Error::Window)?;
 
     let device_extensions = DeviceExtensions {
         khr_swapchain: true,
         ..DeviceExtensions::empty()
     };
-    let (physical_device, queue_family_index) = instance
-        .enumerate_physical_devices()
-        .unwrap()
-        .filter(|p| p.supported_extensions().contains(&device_extensions))
-        .filter_map(|p| {
-            p.queue_family_properties()
-                .iter()
-                .enumerate()
-                .position(|(i, q)| {
-                    q.queue_flags.graphics && p.surface_support(i as u32, &surface).unwrap_or(false)
-                })
-                .map(|i| (p, i as u32))
-        })
-        .min_by_key(|(p, _)| match p.properties().device_type {
-            PhysicalDeviceType::DiscreteGpu => 0,
-            PhysicalDeviceType::IntegratedGpu => 1,
-            PhysicalDeviceType::VirtualGpu => 2,
-            PhysicalDeviceType::Cpu => 3,
-            PhysicalDeviceType::Other => 4,
-            _ => 5,
-        })
-        .unwrap();
+    let features = Features {
+        fill_mode_non_solid: true,
+        ..Features::empty()
+    };
+    let (physical_device, queue_family_index) =
+        pick_device(&instance, &device_extensions, &features, Some(&surface))?;
 
     println!(
         "Using device: {} (type: {:?})",
@@ -121,14 +118,11 @@ fn main() {
                 queue_family_index,
                 ..Default::default()
             }],
-            enabled_features: Features {
-                fill_mode_non_solid: true,
-                ..Default::default()
-            },
+            enabled_features: features,
             ..Default::default()
         },
     )
-    .unwrap();
+    .map_err(Error::Device)?;
 
     let queue = queues.next().unwrap();
 
@@ -136,14 +130,20 @@ fn main() {
         let surface_capabilities = device
             .physical_device()
             .surface_capabilities(&surface, Default::default())
-            .unwrap();
-        let image_format = Some(
-            device
+            .map_err(Error::Surface)?;
+        let (image_format, image_color_space) = pick_surface_format(
+            &device
                 .physical_device()
                 .surface_formats(&surface, Default::default())
-                .unwrap()[0]
-                .0,
+                .map_err(Error::Surface)?,
         );
+        println!(
+            "Swapchain format: {:?} in {:?}",
+            image_format, image_color_space
+        );
+        if image_format.type_color() != Some(NumericType::SRGB) {
+            println!("No sRGB swapchain format, the shaders encode the colour instead");
+        }
         let window = surface.object().unwrap().downcast_ref::<Window>().unwrap();
 
         Swapchain::new(
@@ -151,7 +151,8 @@ fn main() {
             surface.clone(),
             SwapchainCreateInfo {
                 min_image_count: surface_capabilities.min_image_count,
-                image_format,
+                image_format: Some(image_format),
+                image_color_space,
                 image_extent: window.inner_size().into(),
                 image_usage: ImageUsage {
                     color_attachment: true,
@@ -166,225 +167,242 @@ fn main() {
                 ..Default::default()
             },
         )
-        .unwrap()
+        .map_err(Error::Swapchain)?
     };
 
-    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
-
-    let mut vertex_data = vec![];
-    let input = BufReader::new(File::open("monke.obj").unwrap());
-    let monke: Obj = load_obj(input).unwrap();
-    for i in monke.vertices {
-        vertex_data.push(Vertex {
-            position: i.position,
-            normal: i.normal,
-        });
+    let mut renderer = Renderer::new(
+        device.clone(),
+        queue.clone(),
+        swapchain.image_format(),
+        &scene,
+        &assets,
+    )?;
+    let mut material_grid = args.material_grid;
+    if material_grid {
+        show_scene(&mut renderer, &assets, &scene, material_grid)?;
     }
-    let index_data = monke.indices;
-
-    let vertex_buffer = CpuAccessibleBuffer::from_iter(
-        &memory_allocator,
-        BufferUsage {
-            vertex_buffer: true,
-            ..BufferUsage::empty()
-        },
-        false,
-        vertex_data,
-    )
-    .unwrap();
-
-    let index_buffer = CpuAccessibleBuffer::from_iter(
-        &memory_allocator,
-        BufferUsage {
-            index_buffer: true,
-            ..BufferUsage::empty()
-        },
-        false,
-        index_data,
-    )
-    .unwrap();
-
-    let mut instances = [
-        MonkeInstance {
-            transform: [0.0, 0.0, -320.0],
-            colour: [0.46, 0.15, 0.58],
-            scale: 135.0,
-        },
-        MonkeInstance {
-            transform: [0.0, 0.0, -160.0],
-            colour: [0.14, 0.71, 0.95],
-            scale: 64.0,
-        },
-        MonkeInstance {
-            transform: [0.0, 0.0, -80.0],
-            colour: [0.66, 0.31, 0.64],
-            scale: 32.0,
-        },
-        MonkeInstance {
-            transform: [0.0, 0.0, -40.0],
-            colour: [0.00, 0.81, 0.73],
-            scale: 16.0,
-        },
-        MonkeInstance {
-            transform: [0.0, 0.0, -20.0],
-            colour: [0.10, 0.89, 0.67],
-            scale: 8.0,
-        },
-        MonkeInstance {
-            transform: [0.0, 0.0, -10.0],
-            colour: [0.02, 0.71, 0.86],
-            scale: 4.0,
-        },
-        MonkeInstance {
-            transform: [0.0, 0.0, -5.0],
-            colour: [0.72, 0.04, 0.13],
-            scale: 2.0,
-        },
-        MonkeInstance {
-            transform: [0.0, 0.0, 0.0],
-            colour: [0.0, 1.0, 0.0],
-            scale: 0.4,
-        },
-    ];
-
-    instances.reverse();
-
-    let instance_buffer = CpuAccessibleBuffer::from_iter(
-        &memory_allocator,
-        BufferUsage {
-            vertex_buffer: true,
-            ..BufferUsage::empty()
-        },
-        false,
-        instances,
-    )
-    .unwrap();
-
-    let vs_uniform_buffer = CpuBufferPool::<vs::ty::Data>::new(
-        memory_allocator.clone(),
-        BufferUsage {
-            uniform_buffer: true,
-            ..BufferUsage::empty()
-        },
-        MemoryUsage::Upload,
-    );
-
-    let fs_uniform_buffer = CpuBufferPool::<fs::ty::Data>::new(
-        memory_allocator.clone(),
-        BufferUsage {
-            uniform_buffer: true,
-            ..BufferUsage::empty()
-        },
-        MemoryUsage::Upload,
-    );
 
-    let vs = vs::load(device.clone()).unwrap();
-    let fs = fs::load(device.clone()).unwrap();
-
-    let render_pass_1 = vulkano::single_pass_renderpass!(device.clone(),
-        attachments: {
-            intermediary: {
-                load: Clear,
-                store: DontCare,
-                format: swapchain.image_format(),
-                samples: 8,     // This has to match the image definition.
-            },
-            color: {
-                load: Clear,
-                store: Store,
-                format: swapchain.image_format(),
-                samples: 1,
-            },
-            depth: {
-                load: Clear,
-                store: DontCare,
-                format: Format::D16_UNORM,
-                samples: 8,
-            }
-        },
-        pass: {
-            color: [intermediary],
-            depth_stencil: {depth},
-            resolve: [color]
-        }
-    )
-    .unwrap();
-
-    let render_pass_2 = vulkano::single_pass_renderpass!(device.clone(),
-        attachments: {
-            color: {
-                load: Clear,
-                store: Store,
-                format: swapchain.image_format(),
-                samples: 1,
-            },
-            depth: {
-                load: Clear,
-                store: DontCare,
-                format: Format::D16_UNORM,
-                samples: 1,
-            }
-        },
-        pass: {
-            color: [color],
-            depth_stencil: {depth}
-        }
-    )
-    .unwrap();
-
-    let (mut pipeline, mut framebuffers) = window_size_dependent_setup(
-        &memory_allocator,
-        &vs,
-        &fs,
-        &images,
-        render_pass_2.clone(),
-        Levels::ONE,
-    );
+    let (mut pipelines, mut targets) =
+        renderer.window_size_dependent_setup(&images, Levels::ONE, DrawMode::Solid)?;
     let mut recreate_swapchain = false;
 
     let mut previous_frame_end = Some(now(device.clone()).boxed());
-    let rotation_start = Instant::now();
-
-    let descriptor_set_allocator = StandardDescriptorSetAllocator::new(device.clone());
-    let command_buffer_allocator =
-        StandardCommandBufferAllocator::new(device.clone(), Default::default());
+    let mut animation_time = 0.0;
+    let mut paused = false;
 
     let mut level = Levels::ONE;
+    let mut draw_mode = DrawMode::Solid;
+    let mut camera_index = 0;
+    let mut selected_light = 0;
+    let start = &renderer.cameras[0];
+    let mut camera = Camera::new(start.eye, start.yaw, start.pitch);
+    let mut scene_watcher = FileWatcher::new(watched_files(&scene, &renderer));
+    let mut shader_watcher = args
+        .dev_shaders
+        .then(|| FileWatcher::new(runtime::sources()));
+    let mut cursor_grabbed = false;
+    let mut last_frame = Instant::now();
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent { event, .. } => match event {
             WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
             WindowEvent::Resized(_) => {
                 recreate_swapchain = true;
             }
+            WindowEvent::Focused(false) => {
+                cursor_grabbed = false;
+                grab_cursor(&surface, false);
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                camera.mouse_button(button, state);
+                if camera.mode == CameraMode::FreeFly
+                    && button == MouseButton::Left
+                    && state == ElementState::Pressed
+                {
+                    cursor_grabbed = true;
+                    grab_cursor(&surface, true);
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => camera.scroll(match delta {
+                MouseScrollDelta::LineDelta(_, lines) => lines,
+                MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+            }),
             WindowEvent::KeyboardInput { input, .. } => match input.virtual_keycode {
-                Some(input) => match input {
-                    VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit,
-                    VirtualKeyCode::Key1 => {
-                        level = Levels::ONE;
-                        recreate_swapchain = true;
-                    }
-                    VirtualKeyCode::Key2 => {
-                        level = Levels::TWO;
-                        recreate_swapchain = true;
-                    }
-                    VirtualKeyCode::Key3 => {
-                        level = Levels::THREE;
-                        recreate_swapchain = true;
-                    }
-                    VirtualKeyCode::Key4 => {
-                        level = Levels::FOUR;
-                        recreate_swapchain = true;
+                Some(key) => {
+                    camera.key(key, input.state);
+                    if input.state == ElementState::Released {
+                        return;
                     }
-                    VirtualKeyCode::Key5 => {
-                        level = Levels::FIVE;
-                        recreate_swapchain = true;
+                    match key {
+                        VirtualKeyCode::Escape if cursor_grabbed => {
+                            cursor_grabbed = false;
+                            grab_cursor(&surface, false);
+                        }
+                        VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit,
+                        VirtualKeyCode::C => {
+                            camera.toggle_mode();
+                            cursor_grabbed = false;
+                            grab_cursor(&surface, false);
+                        }
+                        VirtualKeyCode::P => paused = !paused,
+                        VirtualKeyCode::V => {
+                            camera_index = (camera_index + 1) % renderer.cameras.len();
+                            let start = &renderer.cameras[camera_index];
+                            camera = Camera::new(start.eye, start.yaw, start.pitch);
+                        }
+                        VirtualKeyCode::L => {
+                            draw_mode = draw_mode.next();
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::M => match renderer.next_samples() {
+                            Ok(Some(samples)) => {
+                                println!("MSAA {}x", samples as u32);
+                                recreate_swapchain = true;
+                            }
+                            Ok(None) => println!("This device can't multisample"),
+                            Err(e) => eprintln!("Keeping MSAA {}x: {}", renderer.samples as u32, e),
+                        },
+                        VirtualKeyCode::G => {
+                            material_grid = !material_grid;
+                            match show_scene(&mut renderer, &assets, &scene, material_grid) {
+                                Ok(()) => {
+                                    scene_watcher.watch(watched_files(&scene, &renderer));
+                                    camera_index = 0;
+                                    let start = &renderer.cameras[0];
+                                    camera = Camera::new(start.eye, start.yaw, start.pitch);
+                                }
+                                Err(e) => eprintln!("Failed to switch scenes: {}", e),
+                            }
+                        }
+                        VirtualKeyCode::N if !renderer.lights.is_empty() => {
+                            selected_light = (selected_light + 1) % renderer.lights.len();
+                            println!(
+                                "Selected light {}: {:?}",
+                                selected_light, renderer.lights[selected_light]
+                            );
+                        }
+                        VirtualKeyCode::Left
+                        | VirtualKeyCode::Right
+                        | VirtualKeyCode::Up
+                        | VirtualKeyCode::Down
+                        | VirtualKeyCode::PageUp
+                        | VirtualKeyCode::PageDown => {
+                            let direction = match key {
+                                VirtualKeyCode::Left => -Vec3::X,
+                                VirtualKeyCode::Right => Vec3::X,
+                                VirtualKeyCode::Up => -Vec3::Z,
+                                VirtualKeyCode::Down => Vec3::Z,
+                                VirtualKeyCode::PageUp => Vec3::Y,
+                                _ => -Vec3::Y,
+                            };
+                            renderer.move_light(selected_light, direction * LIGHT_STEP);
+                        }
+                        VirtualKeyCode::H => {
+                            renderer.cluster_heatmap = !renderer.cluster_heatmap;
+                            if level < Levels::NINE {
+                                println!("The cluster heatmap only shows from level 9 on");
+                            }
+                        }
+                        VirtualKeyCode::B => {
+                            renderer.normal_mapping = !renderer.normal_mapping;
+                            if level < Levels::TEN {
+                                println!("Normal maps are only used from level 10 on");
+                            }
+                        }
+                        VirtualKeyCode::T => {
+                            renderer.tonemapper = renderer.tonemapper.next();
+                            println!("Tonemapper: {:?}", renderer.tonemapper);
+                            if level < Levels::TWELVE {
+                                println!("Tonemapping is only used from level 12 on");
+                            }
+                        }
+                        VirtualKeyCode::E => {
+                            renderer.auto_exposure = !renderer.auto_exposure;
+                            println!(
+                                "Auto exposure {}",
+                                if renderer.auto_exposure { "on" } else { "off" }
+                            );
+                        }
+                        VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+                            let step = match key {
+                                VirtualKeyCode::LBracket => -EXPOSURE_STEP,
+                                _ => EXPOSURE_STEP,
+                            };
+                            renderer.exposure += step;
+                            println!("Exposure {:+.1} EV", renderer.exposure);
+                        }
+                        VirtualKeyCode::I => {
+                            renderer.gamma_comparison = !renderer.gamma_comparison;
+                            match renderer.gamma_comparison {
+                                true => println!(
+                                    "Gamma comparison: without sRGB encoding on the left, with it \
+                                     on the right"
+                                ),
+                                false => println!("Gamma comparison off"),
+                            }
+                        }
+                        VirtualKeyCode::F => {
+                            let [width, height] = swapchain.image_extent();
+                            camera.frame(renderer.bounds, width as f32 / height as f32);
+                        }
+                        VirtualKeyCode::Key1 => {
+                            level = Levels::ONE;
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::Key2 => {
+                            level = Levels::TWO;
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::Key3 => {
+                            level = Levels::THREE;
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::Key4 => {
+                            level = Levels::FOUR;
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::Key5 => {
+                            level = Levels::FIVE;
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::Key6 => {
+                            level = Levels::SIX;
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::Key7 => {
+                            level = Levels::SEVEN;
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::Key8 => {
+                            level = Levels::EIGHT;
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::Key9 => {
+                            level = Levels::NINE;
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::Key0 => {
+                            level = Levels::TEN;
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::Minus => {
+                            level = Levels::ELEVEN;
+                            recreate_swapchain = true;
+                        }
+                        VirtualKeyCode::Equals => {
+                            level = Levels::TWELVE;
+                            recreate_swapchain = true;
+                        }
+                        _ => {}
                     }
-                    _ => {}
-                },
+                }
                 None => {}
             },
             _ => {}
         },
+        Event::DeviceEvent {
+            event: DeviceEvent::MouseMotion { delta },
+            ..
+        } if cursor_grabbed || camera.mode == CameraMode::Orbit => camera.mouse_motion(delta),
         Event::RedrawEventsCleared => {
             let window = surface.object().unwrap().downcast_ref::<Window>().unwrap();
             let dimensions = window.inner_size();
@@ -392,8 +410,33 @@ fn main() {
                 return;
             }
 
+            let now_instant = Instant::now();
+            let delta = (now_instant - last_frame).as_secs_f32();
+            camera.update(delta);
+            renderer.frame_time = delta;
+            if !paused {
+                animation_time += delta;
+            }
+            last_frame = now_instant;
+
             previous_frame_end.as_mut().unwrap().cleanup_finished();
 
+            if scene_watcher.changed() {
+                match Scene::load(&assets, &args.scene)
+                    .map_err(Error::Scene)
+                    .and_then(|new_scene| {
+                        show_scene(&mut renderer, &assets, &new_scene, material_grid)
+                            .map(|()| new_scene)
+                    }) {
+                    Ok(new_scene) => {
+                        scene_watcher.watch(watched_files(&new_scene, &renderer));
+                        scene = new_scene;
+                        println!("Reloaded {}", args.scene.display());
+                    }
+                    Err(e) => eprintln!("Keeping the previous scene: {}", e),
+                }
+            }
+
             if recreate_swapchain {
                 let (new_swapchain, new_images) = match swapchain.recreate(SwapchainCreateInfo {
                     image_extent: dimensions.into(),
@@ -401,70 +444,32 @@ fn main() {
                 }) {
                     Ok(r) => r,
                     Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return,
-                    Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
+                    Err(e) => exit_with(Error::Swapchain(e)),
                 };
 
                 swapchain = new_swapchain;
-                let (new_pipeline, new_framebuffers) = window_size_dependent_setup(
-                    &memory_allocator,
-                    &vs,
-                    &fs,
-                    &new_images,
-                    {
-                        if level >= Levels::FOUR {
-                            render_pass_1.clone()
-                        } else {
-                            render_pass_2.clone()
-                        }
-                    },
-                    level,
-                );
-                pipeline = new_pipeline;
-                framebuffers = new_framebuffers;
+                let (new_pipelines, new_targets) = renderer
+                    .window_size_dependent_setup(&new_images, level, draw_mode)
+                    .unwrap_or_else(|e| exit_with(e));
+                pipelines = new_pipelines;
+                targets = new_targets;
                 recreate_swapchain = false;
             }
 
-            let vs_uniform_buffer_subbuffer = {
-                let rotation = Mat3::from_rotation_y((0) as f32);
-
-                let aspect_ratio =
-                    swapchain.image_extent()[0] as f32 / swapchain.image_extent()[1] as f32;
-                let proj = perspective_rh(aspect_ratio);
-                let view = Mat4::look_at_rh(
-                    Vec3::new(0.0, 0.0, 2.0),
-                    Vec3::new(0.0, 0.0, 0.0),
-                    Vec3::new(0.0, -1.0, 0.0),
-                );
-                let scale = Mat4::from_scale(Vec3::new(1.0, 1.0, 1.0));
-
-                let uniform_data = vs::ty::Data {
-                    world: Mat4::from_mat3(rotation).to_cols_array_2d(),
-                    view: (view * scale).to_cols_array_2d(),
-                    proj,
-                    lev_2: (level >= Levels::TWO) as u32,
-                };
-
-                vs_uniform_buffer.from_data(uniform_data).unwrap()
-            };
-
-            let fs_uniform_buffer_subbuffer = {
-                let uniform_data = fs::ty::Data {
-                    lighting: (level >= Levels::FIVE) as u32,
-                };
-
-                fs_uniform_buffer.from_data(uniform_data).unwrap()
-            };
-
-            let layout = pipeline.layout().set_layouts().get(0).unwrap();
-            let set = PersistentDescriptorSet::new(
-                &descriptor_set_allocator,
-                layout.clone(),
-                [
-                    WriteDescriptorSet::buffer(0, vs_uniform_buffer_subbuffer),
-                    WriteDescriptorSet::buffer(1, fs_uniform_buffer_subbuffer),
-                ],
-            )
-            .unwrap();
+            if shader_watcher.as_mut().is_some_and(FileWatcher::changed) {
+                let dimensions = swapchain.image_extent();
+                match reload_shaders(&device, &mut renderer, dimensions, level, draw_mode) {
+                    Ok(new_pipelines) => {
+                        pipelines = new_pipelines;
+                        window.set_title(TITLE);
+                        println!("Reloaded shaders");
+                    }
+                    Err(e) => {
+                        window.set_title(&format!("{} - shader error, see the console", TITLE));
+                        eprintln!("Keeping the previous shaders: {}", e);
+                    }
+                }
+            }
 
             let (image_index, suboptimal, acquire_future) =
                 match acquire_next_image(swapchain.clone(), None) {
@@ -473,67 +478,42 @@ fn main() {
                         recreate_swapchain = true;
                         return;
                     }
-                    Err(e) => panic!("Failed to acquire next image: {:?}", e),
+                    Err(e) => exit_with(Error::Acquire(e)),
                 };
 
             if suboptimal {
                 recreate_swapchain = true;
             }
 
+            if let Err(e) = renderer.prepare(level) {
+                eprintln!("Drawing without the environment: {}", e);
+            }
             let mut builder = AutoCommandBufferBuilder::primary(
-                &command_buffer_allocator,
+                &renderer.command_buffer_allocator,
                 queue.queue_family_index(),
                 CommandBufferUsage::OneTimeSubmit,
             )
-            .unwrap();
-            builder
-                .begin_render_pass(
-                    RenderPassBeginInfo {
-                        clear_values: {
-                            if level >= Levels::FOUR {
-                                vec![
-                                    Some([0.0, 0.2, 0.6, 1.0].into()),
-                                    Some([0.0, 0.2, 0.6, 1.0].into()),
-                                    Some(1f32.into()),
-                                ]
-                            } else {
-                                vec![Some([0.0, 0.2, 0.6, 1.0].into()), Some(1f32.into())]
-                            }
-                        },
-                        ..RenderPassBeginInfo::framebuffer(
-                            framebuffers[image_index as usize].clone(),
-                        )
-                    },
-                    SubpassContents::Inline,
-                )
-                .unwrap()
-                .bind_pipeline_graphics(pipeline.clone())
-                .bind_descriptor_sets(
-                    PipelineBindPoint::Graphics,
-                    pipeline.layout().clone(),
-                    0,
-                    set,
-                )
-                .bind_vertex_buffers(0, (vertex_buffer.clone(), instance_buffer.clone()))
-                .bind_index_buffer(index_buffer.clone())
-                .draw_indexed(
-                    index_buffer.len() as u32,
-                    instance_buffer.len() as u32,
-                    0,
-                    0,
-                    0,
+            .unwrap_or_else(|e| exit_with(Error::CommandBuffer(e)));
+            renderer
+                .draw(
+                    &mut builder,
+                    targets[image_index as usize].clone(),
+                    &pipelines,
+                    level,
+                    camera.view_matrix(),
+                    animation_time,
                 )
-                .unwrap()
-                .end_render_pass()
-                .unwrap();
-            let command_buffer = builder.build().unwrap();
+                .unwrap_or_else(|e| exit_with(e));
+            let command_buffer = builder
+                .build()
+                .unwrap_or_else(|e| exit_with(Error::BuildCommandBuffer(e)));
 
             let future = previous_frame_end
                 .take()
                 .unwrap()
                 .join(acquire_future)
                 .then_execute(queue.clone(), command_buffer)
-                .unwrap()
+                .unwrap_or_else(|e| exit_with(Error::Execute(e)))
                 .then_swapchain_present(
                     queue.clone(),
                     SwapchainPresentInfo::swapchain_image_index(swapchain.clone(), image_index),
@@ -558,101 +538,62 @@ fn main() {
     });
 }
 
-/// This method is called once during initialization, then again whenever the window is resized
-fn window_size_dependent_setup(
-    memory_allocator: &StandardMemoryAllocator,
-    vs: &ShaderModule,
-    fs: &ShaderModule,
-    images: &[Arc<SwapchainImage>],
-    render_pass: Arc<RenderPass>,
-    level: Levels,
-) -> (Arc<GraphicsPipeline>, Vec<Arc<Framebuffer>>) {
-    let dimensions = images[0].dimensions().width_height();
-
-    let depth_buffer_multi = ImageView::new_default(
-        AttachmentImage::transient_multisampled(
-            memory_allocator,
-            dimensions,
-            SampleCount::Sample8,
-            Format::D16_UNORM,
-        )
-        .unwrap(),
-    )
-    .unwrap();
+/// The files that reload `scene` when they change: its own and the ones its models read.
+fn watched_files(scene: &Scene, renderer: &Renderer) -> Vec<PathBuf> {
+    let mut files = scene.files();
+    files.extend(renderer.model_files.iter().cloned());
+    files
+}
 
-    let depth_buffer = ImageView::new_default(
-        AttachmentImage::transient(memory_allocator, dimensions, Format::D16_UNORM).unwrap(),
-    )
-    .unwrap();
-
-    let framebuffers = images
-        .iter()
-        .map(|image| {
-            let intermediary = ImageView::new_default(
-                AttachmentImage::transient_multisampled(
-                    memory_allocator,
-                    image.dimensions().width_height(),
-                    SampleCount::Sample8,
-                    image.format(),
-                )
-                .unwrap(),
-            )
-            .unwrap();
-            let view = ImageView::new_default(image.clone()).unwrap();
-            return if level >= Levels::FOUR {
-                Framebuffer::new(
-                    render_pass.clone(),
-                    FramebufferCreateInfo {
-                        attachments: vec![intermediary, view, depth_buffer_multi.clone()],
-                        ..Default::default()
-                    },
-                )
-                .unwrap()
-            } else {
-                Framebuffer::new(
-                    render_pass.clone(),
-                    FramebufferCreateInfo {
-                        attachments: vec![view, depth_buffer.clone()],
-                        ..Default::default()
-                    },
-                )
-                .unwrap()
-            };
-        })
-        .collect::<Vec<_>>();
-
-    let mut pipeline = GraphicsPipeline::start()
-        .vertex_input_state(
-            BuffersDefinition::new()
-                .vertex::<Vertex>()
-                .instance::<MonkeInstance>(),
-        )
-        .vertex_shader(vs.entry_point("main").unwrap(), ())
-        .input_assembly_state(InputAssemblyState::new())
-        .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([
-            Viewport {
-                origin: [0.0, 0.0],
-                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
-                depth_range: 0.0..1.0,
-            },
-        ]))
-        .fragment_shader(fs.entry_point("main").unwrap(), ());
-    if level >= Levels::THREE {
-        pipeline = pipeline.depth_stencil_state(DepthStencilState::simple_depth_test());
-    } else {
-        pipeline = pipeline.depth_stencil_state(DepthStencilState::disabled());
+/// Draws `scene`, or the material grid made from it.
+fn show_scene(
+    renderer: &mut Renderer,
+    assets: &Assets,
+    scene: &Scene,
+    material_grid: bool,
+) -> Result<(), Error> {
+    match material_grid {
+        true => renderer.load_scene(&scene.material_grid(), assets),
+        false => renderer.load_scene(scene, assets),
     }
+}
 
-    if level >= Levels::FOUR {
-        pipeline = pipeline.multisample_state(MultisampleState {
-            rasterization_samples: SampleCount::Sample8,
-            ..Default::default()
-        })
+/// Hides the cursor and keeps it inside the window while the camera is being steered with the
+/// mouse.
+fn grab_cursor(surface: &Surface, grab: bool) {
+    let window = surface.object().unwrap().downcast_ref::<Window>().unwrap();
+    if grab {
+        // Not every platform can lock the cursor in place, confining it works nearly everywhere.
+        window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+            .unwrap_or_else(|e| eprintln!("Failed to grab cursor: {:?}", e));
+    } else {
+        window
+            .set_cursor_grab(CursorGrabMode::None)
+            .unwrap_or_else(|e| eprintln!("Failed to release cursor: {:?}", e));
     }
-    let pipeline = pipeline
-        .render_pass(Subpass::from(render_pass, 0).unwrap())
-        .build(memory_allocator.device().clone())
-        .unwrap();
+    window.set_cursor_visible(!grab);
+}
+
+/// Compiles the shader sources and builds the pipelines with them. On failure the renderer keeps the
+/// shaders it had.
+fn reload_shaders(
+    device: &Arc<Device>,
+    renderer: &mut Renderer,
+    dimensions: [u32; 2],
+    level: Levels,
+    mode: DrawMode,
+) -> Result<Vec<Arc<GraphicsPipeline>>, Error> {
+    let (vs, fs) = runtime::compile(device).map_err(Error::Shader)?;
+    renderer
+        .replace_shaders(vs, fs, dimensions, level, mode)
+        .map_err(Error::Pipeline)
+}
 
-    (pipeline, framebuffers)
+/// Prints `error` and quits. For failures the user can fix, like a broken scene file or a machine
+/// without a suitable device.
+fn exit_with(error: impl Display) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(1);
 }
@@ -0,0 +1,358 @@
+use super::{Material, Mesh, MeshError, Model, Part, Texture};
+use crate::assets::Asset;
+use crate::shaders::Vertex;
+
+use gltf::buffer::Data;
+use gltf::image::Format;
+use gltf::mesh::Mode;
+use gltf::Node;
+use math::Mat4;
+use std::path::{Path, PathBuf};
+
+/// Loads the default scene of a .gltf or .glb file, or the first one if there is no default. The
+/// node hierarchy is flattened into the transforms of the parts. Cameras only keep where they are
+/// and where they look, the demo has a projection of its own. A built-in file has to carry its
+/// buffers inside it and its images in buffer views.
+pub fn load(asset: &Asset) -> Result<Model, MeshError> {
+    let (document, buffers, images) = match asset {
+        Asset::File(path) => gltf::import(path),
+        Asset::Embedded(_, contents) => gltf::import_slice(contents),
+    }
+    .map_err(MeshError::Gltf)?;
+
+    let mut model = Model {
+        parts: vec![],
+        textures: images.into_iter().map(texture).collect(),
+        cameras: vec![],
+        files: match asset {
+            Asset::File(path) => external_files(&document, path),
+            Asset::Embedded(..) => vec![],
+        },
+    };
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next());
+    for node in scene.iter().flat_map(|scene| scene.nodes()) {
+        add_node(&mut model, &buffers, node, Mat4::IDENTITY);
+    }
+    Ok(model)
+}
+
+/// The buffers and images that `path` refers to by URI rather than carrying them inside it.
+fn external_files(document: &gltf::Document, path: &Path) -> Vec<PathBuf> {
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let buffers = document
+        .buffers()
+        .filter_map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => Some(uri),
+            gltf::buffer::Source::Bin => None,
+        });
+    let images = document.images().filter_map(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+    buffers
+        .chain(images)
+        .filter(|uri| !uri.starts_with("data:"))
+        .map(|uri| base.join(percent_decode(uri)))
+        .collect()
+}
+
+/// URIs escape spaces and other characters in file names as `%XX`.
+fn percent_decode(uri: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = uri.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn add_node(model: &mut Model, buffers: &[Data], node: Node, parent: Mat4) {
+    let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    if let Some(camera) = node.camera() {
+        eprintln!(
+            "Ignoring the projection of camera {}, only its position and direction are used",
+            camera
+                .name()
+                .map_or_else(|| camera.index().to_string(), str::to_string)
+        );
+        model.cameras.push(transform);
+    }
+
+    if let Some(mesh) = node.mesh() {
+        let name = mesh.name().unwrap_or_default();
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                eprintln!(
+                    "Skipping {:?} in mesh {}, only triangles are supported",
+                    primitive.mode(),
+                    name
+                );
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                Some(positions) => positions.collect(),
+                None => continue,
+            };
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            if indices
+                .iter()
+                .any(|&index| index as usize >= positions.len())
+            {
+                eprintln!(
+                    "Skipping a primitive of mesh {} with indices out of range",
+                    name
+                );
+                continue;
+            }
+
+            let mut mesh = Mesh {
+                vertices: positions
+                    .into_iter()
+                    .map(|position| Vertex {
+                        position,
+                        normal: [0.0; 3],
+                        uv: [0.0; 2],
+                        tangent: [0.0; 4],
+                    })
+                    .collect(),
+                indices,
+            };
+            // Only the first set, the materials don't say which one their textures use.
+            let tex_coords = reader.read_tex_coords(0);
+            let has_tex_coords = tex_coords.is_some();
+            for (vertex, uv) in mesh.vertices.iter_mut().zip(
+                tex_coords
+                    .into_iter()
+                    .flat_map(|tex_coords| tex_coords.into_f32()),
+            ) {
+                vertex.uv = uv;
+            }
+            let tangents = reader.read_tangents();
+            let has_tangents = tangents.is_some();
+            for (vertex, tangent) in mesh.vertices.iter_mut().zip(tangents.into_iter().flatten()) {
+                vertex.tangent = tangent;
+            }
+            match reader.read_normals() {
+                Some(normals) => {
+                    for (vertex, normal) in mesh.vertices.iter_mut().zip(normals) {
+                        vertex.normal = normal;
+                    }
+                }
+                None => {
+                    // glTF asks for flat normals, so no vertex may be shared between triangles.
+                    mesh.vertices = mesh
+                        .indices
+                        .iter()
+                        .map(|&index| mesh.vertices[index as usize])
+                        .collect();
+                    mesh.indices = (0..mesh.vertices.len() as u32).collect();
+                    mesh.fill_missing_normals();
+                }
+            }
+            // Without texture coordinates there is nothing for a normal map to follow.
+            if has_tex_coords && !has_tangents {
+                mesh.generate_tangents();
+            }
+
+            model.parts.push(Part {
+                name: name.to_string(),
+                mesh,
+                transform,
+                material: material(primitive.material()),
+            });
+        }
+    }
+
+    for child in node.children() {
+        add_node(model, buffers, child, transform);
+    }
+}
+
+/// The specular colour and shininess are made up from the metallic and roughness: metals reflect
+/// in their own colour, everything else in about 4% white, and rougher surfaces get wider
+/// highlights.
+fn material(material: gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, _] = pbr.base_color_factor();
+    let metallic = pbr.metallic_factor();
+    let roughness = pbr.roughness_factor();
+    Material {
+        base_colour: pbr.base_color_factor(),
+        metallic,
+        roughness,
+        specular: [r, g, b].map(|channel| 0.04 + (channel - 0.04) * metallic),
+        // A perfect mirror would need an infinite exponent.
+        shininess: (2.0 / roughness.max(0.05).powi(4) - 2.0).max(1.0),
+        emissive: material.emissive_factor(),
+        base_colour_texture: pbr
+            .base_color_texture()
+            .map(|info| info.texture().source().index()),
+        normal_texture: material
+            .normal_texture()
+            .map(|normal| normal.texture().source().index()),
+        metallic_roughness_texture: pbr
+            .metallic_roughness_texture()
+            .map(|info| info.texture().source().index()),
+        specular_texture: None,
+    }
+}
+
+/// Expands the 8 bit formats to RGBA. Anything else becomes white, so that the indices of the
+/// other textures stay valid.
+fn texture(image: gltf::image::Data) -> Texture {
+    let channels = match image.format {
+        Format::R8 => 1,
+        Format::R8G8 => 2,
+        Format::R8G8B8 => 3,
+        Format::R8G8B8A8 => 4,
+        format => {
+            eprintln!(
+                "Unsupported texture format {:?}, using white instead",
+                format
+            );
+            return Texture::white();
+        }
+    };
+    let pixels = image
+        .pixels
+        .chunks_exact(channels)
+        .flat_map(|pixel| match *pixel {
+            [l] => [l, l, l, 255],
+            [l, a] => [l, l, l, a],
+            [r, g, b] => [r, g, b, 255],
+            [r, g, b, a] => [r, g, b, a],
+            _ => unreachable!(),
+        })
+        .collect();
+    Texture {
+        width: image.width,
+        height: image.height,
+        pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::Vec3;
+
+    /// A red textured triangle two levels down, scaled by its node and moved by the parent, and a
+    /// camera next to it.
+    const NESTED: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "translation": [1, 0, 0], "children": [1, 2] },
+            { "name": "child", "scale": [2, 2, 2], "mesh": 0 },
+            { "translation": [0, 0, 5], "camera": 0 }
+        ],
+        "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.8, "znear": 0.1 } }],
+        "meshes": [{
+            "name": "triangle",
+            "primitives": [{ "attributes": { "POSITION": 0, "TEXCOORD_0": 1 }, "material": 0 }]
+        }],
+        "materials": [{
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [1, 0.5, 0.25, 1],
+                "baseColorTexture": { "index": 0 },
+                "metallicFactor": 0.5,
+                "roughnessFactor": 0.25
+            }
+        }],
+        "textures": [{ "source": 0 }],
+        "images": [{ "bufferView": 2, "mimeType": "image/png" }],
+        "accessors": [
+            {
+                "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [0, 0, 0], "max": [1, 1, 0]
+            },
+            { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" }
+        ],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 24 },
+            { "buffer": 0, "byteOffset": 60, "byteLength": 69 }
+        ],
+        "buffers": [{
+            "byteLength": 129,
+            "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAIAAACQd1PeAAAADElEQVR4nGP4z8AAAAMBAQDJ/pLvAAAAAElFTkSuQmCC"
+        }]
+    }"#;
+
+    #[test]
+    fn nested_nodes_test() {
+        let model = load(&Asset::Embedded("nested.gltf", NESTED.as_bytes())).unwrap();
+
+        assert_eq!(model.parts.len(), 1);
+        let part = &model.parts[0];
+        assert_eq!(part.name, "triangle");
+        assert_eq!(part.mesh.indices, [0, 1, 2]);
+        assert_eq!(part.mesh.vertices[1].uv, [1.0, 0.0]);
+        // Scaled by the child, then moved by the parent.
+        let corner = part
+            .transform
+            .transform_point3(Vec3::from(part.mesh.vertices[1].position));
+        assert_eq!(corner, Vec3::new(3.0, 0.0, 0.0));
+        // Generated, as the file has no normals.
+        assert_eq!(part.mesh.vertices[0].normal, [0.0, 0.0, 1.0]);
+
+        assert_eq!(model.cameras.len(), 1);
+        assert_eq!(
+            model.cameras[0].transform_point3(Vec3::ZERO),
+            Vec3::new(1.0, 0.0, 5.0)
+        );
+        assert!(model.files.is_empty());
+    }
+
+    #[test]
+    fn material_test() {
+        let model = load(&Asset::Embedded("nested.gltf", NESTED.as_bytes())).unwrap();
+        let material = &model.parts[0].material;
+
+        assert_eq!(material.base_colour, [1.0, 0.5, 0.25, 1.0]);
+        assert_eq!(material.metallic, 0.5);
+        assert_eq!(material.roughness, 0.25);
+        assert!(Vec3::from(material.specular).abs_diff_eq(Vec3::new(0.52, 0.27, 0.145), 1e-6));
+        assert_eq!(material.base_colour_texture, Some(0));
+        assert_eq!(material.normal_texture, None);
+        assert_eq!(material.metallic_roughness_texture, None);
+
+        // The RGB image is expanded to RGBA.
+        assert_eq!(model.textures.len(), 1);
+        let texture = &model.textures[0];
+        assert_eq!([texture.width, texture.height], [1, 1]);
+        assert_eq!(texture.pixels, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn percent_decode_test() {
+        assert_eq!(percent_decode("plain.bin"), "plain.bin");
+        assert_eq!(percent_decode("my%20model%2Fpart.bin"), "my model/part.bin");
+        // Not an escape, kept as written.
+        assert_eq!(percent_decode("100%.png"), "100%.png");
+        assert_eq!(percent_decode("%zz%+1%4"), "%zz%+1%4");
+    }
+}
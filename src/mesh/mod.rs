@@ -0,0 +1,256 @@
+//! Meshes and the files they come from. An OBJ file holds objects and groups with MTL materials, a
+//! glTF file a whole node hierarchy of meshes with materials, textures and cameras. Both are loaded
+//! into a `Model`.
+
+mod gltf;
+mod obj;
+
+use crate::assets::{Asset, AssetError, Assets};
+use crate::shaders::Vertex;
+
+use math::{Mat4, Vec3};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Indexed triangle list, ready to be uploaded.
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+/// Everything loaded from one mesh file.
+pub struct Model {
+    pub parts: Vec<Part>,
+    pub textures: Vec<Texture>,
+    /// Where the cameras of the file are, relative to the model. They look down their -z axis.
+    pub cameras: Vec<Mat4>,
+    /// The other files on disk the mesh file made the loader read, like MTL libraries, textures
+    /// and glTF buffers.
+    pub files: Vec<PathBuf>,
+}
+
+/// One mesh of a model, placed where the node hierarchy put it.
+pub struct Part {
+    /// The OBJ object or group, or the glTF mesh it came from.
+    pub name: String,
+    pub mesh: Mesh,
+    /// Relative to the model.
+    pub transform: Mat4,
+    pub material: Material,
+}
+
+/// The base colour is drawn on every level, the specular colour and shininess from the specular
+/// level on and the metallic and roughness on the physically based level. The textures are drawn
+/// from the textured level on, each multiplying what it stands for.
+/// glTF files fill in the metallic and roughness, MTL files the specular colour and shininess and,
+/// with the PBR extension, the metallic and roughness too.
+#[derive(Clone, Debug)]
+pub struct Material {
+    /// Multiplies the instance colour.
+    pub base_colour: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub specular: [f32; 3],
+    /// Blinn-Phong exponent.
+    pub shininess: f32,
+    /// Light given off by the surface itself, added on the lit levels.
+    pub emissive: [f32; 3],
+    /// Indices into `Model::textures`.
+    pub base_colour_texture: Option<usize>,
+    /// Tangent space, with +y going up the image.
+    pub normal_texture: Option<usize>,
+    /// Roughness in green and metallic in blue, the way glTF stores them.
+    pub metallic_roughness_texture: Option<usize>,
+    pub specular_texture: Option<usize>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_colour: [1.0; 4],
+            metallic: 0.0,
+            roughness: 0.5,
+            // What Blender writes for a new material.
+            specular: [0.5; 3],
+            shininess: 32.0,
+            emissive: [0.0; 3],
+            base_colour_texture: None,
+            normal_texture: None,
+            metallic_roughness_texture: None,
+            specular_texture: None,
+        }
+    }
+}
+
+/// RGBA, 8 bits per channel.
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Texture {
+    pub fn white() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            pixels: vec![255; 4],
+        }
+    }
+
+    /// A normal map that leaves the normals as they are.
+    pub fn flat_normal() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            pixels: vec![128, 128, 255, 255],
+        }
+    }
+
+    /// Decodes a PNG or JPEG file, whatever its extension says.
+    pub fn load(asset: &Asset) -> Result<Self, image::ImageError> {
+        let contents = asset.read().map_err(image::ImageError::IoError)?;
+        let image = image::load_from_memory(&contents)?.into_rgba8();
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.into_raw(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+    Io(PathBuf, io::Error),
+    /// File, line and what is wrong with it.
+    Parse(PathBuf, usize, String),
+    Gltf(::gltf::Error),
+    /// A file the mesh file refers to, like an MTL library, could not be found.
+    Asset(AssetError),
+    UnknownFormat,
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            MeshError::Parse(path, line, problem) => {
+                write!(f, "{}:{}: {}", path.display(), line, problem)
+            }
+            MeshError::Gltf(e) => write!(f, "{}", e),
+            MeshError::Asset(e) => write!(f, "{}", e),
+            MeshError::UnknownFormat => write!(f, "unknown format, expected .obj, .gltf or .glb"),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+impl Model {
+    /// Picks the loader from the file extension. Files the mesh file refers to are found through
+    /// `assets`.
+    pub fn load(asset: &Asset, assets: &Assets) -> Result<Model, MeshError> {
+        let path = asset.path();
+        let extension = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("obj") => obj::load(asset, assets),
+            Some("gltf" | "glb") => gltf::load(asset),
+            _ => Err(MeshError::UnknownFormat),
+        }
+    }
+}
+
+impl Mesh {
+    /// Gives every vertex without a normal, meaning all zeros, the average of the triangles
+    /// around it weighted by their area. Vertices that are not shared come out flat.
+    pub fn fill_missing_normals(&mut self) {
+        let missing: Vec<bool> = self
+            .vertices
+            .iter()
+            .map(|vertex| vertex.normal == [0.0; 3])
+            .collect();
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] =
+                [0, 1, 2].map(|i| Vec3::from(self.vertices[triangle[i] as usize].position));
+            // As long as twice the area of the triangle.
+            let normal = (b - a).cross(c - a);
+            for &index in triangle {
+                normals[index as usize] += normal;
+            }
+        }
+        for ((vertex, normal), missing) in self.vertices.iter_mut().zip(normals).zip(missing) {
+            if missing {
+                vertex.normal = normal.normalize_or_zero().into();
+            }
+        }
+    }
+
+    /// Gives every vertex a MikkTSpace tangent, which is what normal maps are baked against.
+    /// Corners that share a vertex can get different tangents, so every corner gets a vertex of its
+    /// own first, and the ones that come out the same are merged again afterwards.
+    pub fn generate_tangents(&mut self) {
+        let mut corners = Corners(
+            self.indices
+                .iter()
+                .map(|&index| self.vertices[index as usize])
+                .collect(),
+        );
+        if self.indices.is_empty() || !bevy_mikktspace::generate_tangents(&mut corners) {
+            return;
+        }
+
+        let mut lookup: HashMap<Vec<u8>, u32> = HashMap::new();
+        self.vertices.clear();
+        self.indices = corners
+            .0
+            .into_iter()
+            .map(|vertex| {
+                *lookup
+                    .entry(bytemuck::bytes_of(&vertex).to_vec())
+                    .or_insert_with(|| {
+                        self.vertices.push(vertex);
+                        (self.vertices.len() - 1) as u32
+                    })
+            })
+            .collect();
+    }
+}
+
+/// The corners of the triangles of a mesh, three by three, the way MikkTSpace wants them.
+struct Corners(Vec<Vertex>);
+
+impl bevy_mikktspace::Geometry for Corners {
+    fn num_faces(&self) -> usize {
+        self.0.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.0[face * 3 + vert].position
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.0[face * 3 + vert].normal
+    }
+
+    /// With v going up, like in the tools the normal maps are baked in, so that the bitangent
+    /// points up the image.
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let [u, v] = self.0[face * 3 + vert].uv;
+        [u, 1.0 - v]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.0[face * 3 + vert].tangent = tangent;
+    }
+}
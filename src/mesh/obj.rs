@@ -0,0 +1,613 @@
+//! Wavefront OBJ files and the MTL material libraries they use.
+//!
+//! Every object (`o`) and group (`g`) becomes its own part, split again wherever `usemtl` switches
+//! to another material. Polygons are triangulated as fans. Corners without a normal get a smooth
+//! one when their face is in a smoothing group (`s 1`), averaged over every face of the group at
+//! that position even across UV seams, and the face normal otherwise. Statements that don't change
+//! what is drawn, like `vp` or `lod`, are skipped.
+
+use super::{Material, Mesh, MeshError, Model, Part, Texture};
+use crate::assets::{Asset, Assets};
+use crate::shaders::Vertex;
+
+use math::{Mat4, Vec3};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The name of the faces before the first `o` or `g`.
+const DEFAULT_GROUP: &str = "default";
+
+struct Face {
+    corners: Vec<Corner>,
+    /// 0 for flat shading.
+    smoothing_group: u32,
+}
+
+#[derive(Copy, Clone)]
+struct Corner {
+    position: usize,
+    tex_coord: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Faces that share a name and a material.
+struct FaceGroup {
+    name: String,
+    material: Option<String>,
+    faces: Vec<Face>,
+}
+
+pub fn load(asset: &Asset, assets: &Assets) -> Result<Model, MeshError> {
+    let path = asset.path();
+    let source = asset
+        .read_to_string()
+        .map_err(|e| MeshError::Io(path.to_path_buf(), e))?;
+    parse(&source, path, assets)
+}
+
+/// Builds the model in `source`, read from `path`. The material libraries are found relative to it.
+fn parse(source: &str, path: &Path, assets: &Assets) -> Result<Model, MeshError> {
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut positions: Vec<Vec3> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut tex_coords: Vec<[f32; 2]> = vec![];
+    let mut libraries = vec![];
+    let mut groups: Vec<FaceGroup> = vec![];
+    let mut group_lookup: HashMap<(String, Option<String>), usize> = HashMap::new();
+    let mut name = DEFAULT_GROUP.to_string();
+    let mut material = None;
+    let mut smoothing_group = 0;
+
+    for (number, line) in source.lines().enumerate() {
+        let error = |problem: String| MeshError::Parse(path.to_path_buf(), number + 1, problem);
+        let mut words = line.split('#').next().unwrap().split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = words.collect();
+
+        match keyword {
+            "v" => positions.push(vector(&args).ok_or_else(|| error(bad_vector("v")))?),
+            "vn" => normals.push(vector(&args).ok_or_else(|| error(bad_vector("vn")))?),
+            "vt" => tex_coords.push(
+                tex_coord(&args)
+                    .ok_or_else(|| error("`vt` needs one to three numbers".to_string()))?,
+            ),
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error("a face needs at least three corners".to_string()));
+                }
+                let corners = args
+                    .iter()
+                    .map(|corner| {
+                        parse_corner(corner, positions.len(), tex_coords.len(), normals.len())
+                            .ok_or_else(|| error(format!("invalid face corner {:?}", corner)))
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                let key = (name.clone(), material.clone());
+                let index = *group_lookup.entry(key).or_insert_with(|| {
+                    groups.push(FaceGroup {
+                        name: name.clone(),
+                        material: material.clone(),
+                        faces: vec![],
+                    });
+                    groups.len() - 1
+                });
+                groups[index].faces.push(Face {
+                    corners,
+                    smoothing_group,
+                });
+            }
+            "o" | "g" => {
+                name = match args.is_empty() {
+                    true => DEFAULT_GROUP.to_string(),
+                    false => args.join(" "),
+                }
+            }
+            "usemtl" => material = Some(args.join(" ")),
+            "s" => {
+                smoothing_group = match args.first() {
+                    None | Some(&"off") => 0,
+                    Some(group) => group
+                        .parse()
+                        .map_err(|_| error(format!("invalid smoothing group {:?}", group)))?,
+                }
+            }
+            "mtllib" => libraries.extend(args.iter().map(|library| base.join(library))),
+            _ => {}
+        }
+    }
+
+    let mut model = Model {
+        parts: vec![],
+        textures: vec![],
+        cameras: vec![],
+        files: vec![],
+    };
+    let mut materials = HashMap::new();
+    for library in &libraries {
+        let loaded = assets
+            .resolve(library)
+            .map_err(MeshError::Asset)
+            .and_then(|library| load_materials(&library, assets, &mut model));
+        match loaded {
+            Ok(library_materials) => materials.extend(library_materials),
+            // The geometry is still worth showing without its materials.
+            Err(e) => eprintln!("Ignoring material library: {}", e),
+        }
+    }
+
+    for group in groups {
+        let material = match &group.material {
+            Some(name) => materials.get(name).cloned().unwrap_or_else(|| {
+                eprintln!("Unknown material {:?} in {}", name, path.display());
+                Material::default()
+            }),
+            None => Material::default(),
+        };
+        model.parts.push(Part {
+            name: group.name,
+            mesh: build_mesh(&positions, &tex_coords, &normals, &group.faces),
+            transform: Mat4::IDENTITY,
+            material,
+        });
+    }
+    Ok(model)
+}
+
+fn vector(args: &[&str]) -> Option<Vec3> {
+    // Positions may have a fourth, weight component.
+    match args {
+        [x, y, z] | [x, y, z, _] => {
+            Some(Vec3::new(x.parse().ok()?, y.parse().ok()?, z.parse().ok()?))
+        }
+        _ => None,
+    }
+}
+
+/// `u`, `u v` or `u v w`. OBJ puts v = 0 at the bottom of the image, the `Vertex` at the top.
+fn tex_coord(args: &[&str]) -> Option<[f32; 2]> {
+    match args {
+        [u] => Some([u.parse().ok()?, 1.0]),
+        [u, v] | [u, v, _] => Some([u.parse().ok()?, 1.0 - v.parse::<f32>().ok()?]),
+        _ => None,
+    }
+}
+
+fn bad_vector(keyword: &str) -> String {
+    format!("`{}` needs three numbers", keyword)
+}
+
+/// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`.
+fn parse_corner(
+    corner: &str,
+    positions: usize,
+    tex_coords: usize,
+    normals: usize,
+) -> Option<Corner> {
+    let mut indices = corner.split('/');
+    let position = resolve(indices.next()?, positions)?;
+    let tex_coord = match indices.next().filter(|index| !index.is_empty()) {
+        Some(tex_coord) => Some(resolve(tex_coord, tex_coords)?),
+        None => None,
+    };
+    let normal = match indices.next().filter(|index| !index.is_empty()) {
+        Some(normal) => Some(resolve(normal, normals)?),
+        None => None,
+    };
+    Some(Corner {
+        position,
+        tex_coord,
+        normal,
+    })
+}
+
+/// Indices start at 1, negative ones count back from the last element read so far.
+fn resolve(index: &str, len: usize) -> Option<usize> {
+    let index: isize = index.parse().ok()?;
+    let resolved = match index {
+        1.. => index - 1,
+        ..=-1 => len as isize + index,
+        0 => return None,
+    };
+    (0..len as isize)
+        .contains(&resolved)
+        .then_some(resolved as usize)
+}
+
+fn build_mesh(
+    positions: &[Vec3],
+    tex_coords: &[[f32; 2]],
+    normals: &[Vec3],
+    faces: &[Face],
+) -> Mesh {
+    /// Which corners can share a vertex.
+    #[derive(Copy, Clone, Eq, Hash, PartialEq)]
+    enum Normal {
+        Given(usize),
+        Smooth(u32),
+        Flat(usize),
+    }
+
+    // As long as twice the area of the face, so bigger faces count for more.
+    let face_normals: Vec<Vec3> = faces
+        .iter()
+        .map(|face| {
+            let corner = |i: usize| positions[face.corners[i].position];
+            (1..face.corners.len() - 1)
+                .map(|i| (corner(i) - corner(0)).cross(corner(i + 1) - corner(0)))
+                .fold(Vec3::ZERO, |sum, normal| sum + normal)
+        })
+        .collect();
+    // Summed per position before the corners are split on their texture coordinates, so that UV
+    // seams don't crease the shading.
+    let mut smooth_normals: HashMap<(usize, u32), Vec3> = HashMap::new();
+    for (face, face_normal) in faces.iter().zip(&face_normals) {
+        if face.smoothing_group != 0 {
+            for corner in face.corners.iter().filter(|corner| corner.normal.is_none()) {
+                *smooth_normals
+                    .entry((corner.position, face.smoothing_group))
+                    .or_insert(Vec3::ZERO) += *face_normal;
+            }
+        }
+    }
+
+    let mut mesh = Mesh {
+        vertices: vec![],
+        indices: vec![],
+    };
+    let mut lookup: HashMap<(usize, Option<usize>, Normal), u32> = HashMap::new();
+    for (face_index, face) in faces.iter().enumerate() {
+        let corners: Vec<u32> = face
+            .corners
+            .iter()
+            .map(|corner| {
+                let normal = match (corner.normal, face.smoothing_group) {
+                    (Some(normal), _) => Normal::Given(normal),
+                    (None, 0) => Normal::Flat(face_index),
+                    (None, group) => Normal::Smooth(group),
+                };
+                let key = (corner.position, corner.tex_coord, normal);
+                *lookup.entry(key).or_insert_with(|| {
+                    mesh.vertices.push(Vertex {
+                        position: positions[corner.position].into(),
+                        normal: match normal {
+                            Normal::Given(index) => normals[index],
+                            Normal::Smooth(group) => {
+                                smooth_normals[&(corner.position, group)].normalize_or_zero()
+                            }
+                            Normal::Flat(index) => face_normals[index].normalize_or_zero(),
+                        }
+                        .into(),
+                        uv: corner.tex_coord.map_or([0.0; 2], |index| tex_coords[index]),
+                        tangent: [0.0; 4],
+                    });
+                    (mesh.vertices.len() - 1) as u32
+                })
+            })
+            .collect();
+        for i in 1..corners.len() - 1 {
+            mesh.indices
+                .extend([corners[0], corners[i], corners[i + 1]]);
+        }
+    }
+    // Without texture coordinates there is nothing for a normal map to follow.
+    if faces
+        .iter()
+        .flat_map(|face| &face.corners)
+        .any(|corner| corner.tex_coord.is_some())
+    {
+        mesh.generate_tangents();
+    }
+    mesh
+}
+
+/// Reads the materials of an MTL file. Their textures are found through `assets`, relative to the
+/// library, and appended to the textures of `model`, and the files to its files.
+fn load_materials(
+    library: &Asset,
+    assets: &Assets,
+    model: &mut Model,
+) -> Result<HashMap<String, Material>, MeshError> {
+    model.files.extend(library.file().map(Path::to_path_buf));
+    let path = library.path();
+    let source = library
+        .read_to_string()
+        .map_err(|e| MeshError::Io(path.to_path_buf(), e))?;
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Material)> = None;
+    let mut texture_lookup: HashMap<PathBuf, usize> = HashMap::new();
+    for (number, line) in source.lines().enumerate() {
+        let error = |problem: String| MeshError::Parse(path.to_path_buf(), number + 1, problem);
+        let mut words = line.split('#').next().unwrap().split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = words.collect();
+
+        if keyword == "newmtl" {
+            materials.extend(current.take());
+            current = Some((args.join(" "), Material::default()));
+            continue;
+        }
+        let material = match &mut current {
+            Some((_, material)) => material,
+            None => continue,
+        };
+        let number = |value: &str| {
+            value
+                .parse::<f32>()
+                .map_err(|_| error(format!("invalid number {:?} for `{}`", value, keyword)))
+        };
+        let value = || number(args.first().copied().unwrap_or_default());
+        let colour = || match args.as_slice() {
+            [r, g, b] => Ok([number(r)?, number(g)?, number(b)?]),
+            // A single value is grey.
+            [value] => Ok([number(value)?; 3]),
+            _ => Err(error(format!("`{}` needs an RGB colour", keyword))),
+        };
+        let mut texture = || -> Result<usize, MeshError> {
+            // Options like `-s 1 1 1` come before the file name.
+            let file = base.join(
+                args.last()
+                    .ok_or_else(|| error(format!("`{}` needs a file", keyword)))?,
+            );
+            Ok(*texture_lookup.entry(file.clone()).or_insert_with(|| {
+                let texture = assets.resolve(&file).map_err(|e| e.to_string());
+                if let Ok(asset) = &texture {
+                    model.files.extend(asset.file().map(Path::to_path_buf));
+                }
+                let texture =
+                    texture.and_then(|asset| Texture::load(&asset).map_err(|e| e.to_string()));
+                model.textures.push(texture.unwrap_or_else(|e| {
+                    eprintln!(
+                        "Failed to load texture {}: {}, using white",
+                        file.display(),
+                        e
+                    );
+                    Texture::white()
+                }));
+                model.textures.len() - 1
+            }))
+        };
+
+        match keyword {
+            "Kd" => {
+                let [r, g, b] = colour()?;
+                material.base_colour = [r, g, b, material.base_colour[3]];
+            }
+            "d" => material.base_colour[3] = value()?,
+            "Tr" => material.base_colour[3] = 1.0 - value()?,
+            "Ks" => material.specular = colour()?,
+            "Ns" => material.shininess = value()?,
+            "Ke" => material.emissive = colour()?,
+            // From the PBR extension of the format.
+            "Pm" => material.metallic = value()?,
+            "Pr" => material.roughness = value()?,
+            "map_Kd" => material.base_colour_texture = Some(texture()?),
+            "norm" => material.normal_texture = Some(texture()?),
+            // Heights rather than normals, which would need the slopes worked out first.
+            "map_Bump" | "bump" => eprintln!(
+                "Ignoring bump map {} in {}, only normal maps (`norm`) are supported",
+                args.last().copied().unwrap_or_default(),
+                path.display()
+            ),
+            "map_Ks" => material.specular_texture = Some(texture()?),
+            _ => {}
+        }
+    }
+    materials.extend(current);
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+    fn load_source(source: &str) -> Model {
+        parse(source, Path::new("test.obj"), &Assets::new(None)).unwrap()
+    }
+
+    fn indices(corner: Option<Corner>) -> Option<(usize, Option<usize>, Option<usize>)> {
+        corner.map(|corner| (corner.position, corner.tex_coord, corner.normal))
+    }
+
+    fn assert_normal(actual: [f32; 3], expected: Vec3) {
+        assert!(
+            Vec3::from(actual).abs_diff_eq(expected, 1e-5),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn resolve_test() {
+        assert_eq!(resolve("1", 3), Some(0));
+        assert_eq!(resolve("3", 3), Some(2));
+        assert_eq!(resolve("-1", 3), Some(2));
+        assert_eq!(resolve("-3", 3), Some(0));
+        assert_eq!(resolve("0", 3), None);
+        assert_eq!(resolve("4", 3), None);
+        assert_eq!(resolve("-4", 3), None);
+        assert_eq!(resolve("1", 0), None);
+        assert_eq!(resolve("one", 3), None);
+    }
+
+    #[test]
+    fn parse_corner_test() {
+        assert_eq!(indices(parse_corner("2", 3, 3, 3)), Some((1, None, None)));
+        assert_eq!(
+            indices(parse_corner("2/3", 3, 3, 3)),
+            Some((1, Some(2), None))
+        );
+        assert_eq!(
+            indices(parse_corner("2//1", 3, 3, 3)),
+            Some((1, None, Some(0)))
+        );
+        assert_eq!(
+            indices(parse_corner("-1/-2/-3", 3, 3, 3)),
+            Some((2, Some(1), Some(0)))
+        );
+        assert_eq!(indices(parse_corner("2/4", 3, 3, 3)), None);
+        assert_eq!(indices(parse_corner("2//1", 3, 3, 0)), None);
+        assert_eq!(indices(parse_corner("", 3, 3, 3)), None);
+    }
+
+    #[test]
+    fn fan_triangulation_test() {
+        let model = load_source("v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1 2 3 4 5\n");
+        let mesh = &model.parts[0].mesh;
+
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 0, 3, 4]);
+    }
+
+    #[test]
+    fn groups_test() {
+        let model = load_source(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             f 1 2 3\n\
+             o first\nf 1 2 3\n\
+             usemtl red\nf 1 2 3\n\
+             g second\nf 1 2 3\n\
+             o first\nf 1 2 3\n",
+        );
+        let parts: Vec<(&str, usize)> = model
+            .parts
+            .iter()
+            .map(|part| (part.name.as_str(), part.mesh.indices.len() / 3))
+            .collect();
+
+        assert_eq!(
+            parts,
+            [
+                (DEFAULT_GROUP, 1),
+                ("first", 1),
+                ("first", 2),
+                ("second", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn smoothing_groups_test() {
+        // Two triangles at a right angle sharing the edge from 1 to 2, with different texture
+        // coordinates on either side of it.
+        let faces = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nvt 0 0\nvt 1 1\n\
+                     f 1/1 2/1 3/1\nf 2/2 1/2 4/2\n";
+        let normals = |smoothing: &str| {
+            let mesh = &load_source(&format!("{}{}", smoothing, faces)).parts[0].mesh;
+            mesh.vertices
+                .iter()
+                .map(|vertex| (vertex.position, vertex.normal))
+                .collect::<Vec<_>>()
+        };
+
+        let smooth = normals("s 1\n");
+        assert_eq!(smooth.len(), 6);
+        for (position, normal) in smooth {
+            let expected = match position {
+                [0.0, 1.0, 0.0] => Vec3::Z,
+                [0.0, 0.0, 1.0] => Vec3::Y,
+                _ => Vec3::new(0.0, 1.0, 1.0).normalize(),
+            };
+            assert_normal(normal, expected);
+        }
+
+        let flat = normals("s off\n");
+        assert_eq!(flat.len(), 6);
+        for (position, normal) in flat {
+            let expected = match position {
+                [0.0, 1.0, 0.0] => Vec3::Z,
+                [0.0, 0.0, 1.0] => Vec3::Y,
+                // The shared corners keep the normal of their own face.
+                _ => match normal[2] > normal[1] {
+                    true => Vec3::Z,
+                    false => Vec3::Y,
+                },
+            };
+            assert_normal(normal, expected);
+        }
+    }
+
+    #[test]
+    fn given_normals_test() {
+        let model = load_source("v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 -1\ns 1\nf 1//1 2//1 3//1\n");
+
+        for vertex in &model.parts[0].mesh.vertices {
+            assert_normal(vertex.normal, Vec3::NEG_Z);
+        }
+    }
+
+    #[test]
+    fn materials_test() {
+        let dir = env::temp_dir().join(format!("rendering_demo_obj_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("test.mtl"),
+            "# Comment\n\
+             newmtl red\nKd 1 0 0\nd 0.5\nKs 0.2\nNs 10\nKe 0 0 1\nPm 0.25\nPr 0.75\n\
+             map_Kd -s 1 1 1 missing.png\n\
+             newmtl green\nKd 0 1 0\nTr 0.25\nnorm missing.png\n\
+             newmtl blue\nKd 0 0 1\nmap_Bump missing.png\n",
+        )
+        .unwrap();
+        // The built-in model finds its library in the asset root.
+        let source = format!(
+            "mtllib test.mtl\n{}usemtl red\nf 1 2 3\nusemtl green\nf 1 3 2\nusemtl blue\nf 2 1 3\n",
+            TRIANGLE
+        );
+        let model = parse(
+            &source,
+            Path::new("test.obj"),
+            &Assets::new(Some(dir.clone())),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        let model = model.unwrap();
+
+        let red = &model.parts[0].material;
+        assert_eq!(red.base_colour, [1.0, 0.0, 0.0, 0.5]);
+        assert_eq!(red.specular, [0.2; 3]);
+        assert_eq!(red.shininess, 10.0);
+        assert_eq!(red.emissive, [0.0, 0.0, 1.0]);
+        assert_eq!(red.metallic, 0.25);
+        assert_eq!(red.roughness, 0.75);
+        assert_eq!(red.base_colour_texture, Some(0));
+
+        let green = &model.parts[1].material;
+        assert_eq!(green.base_colour, [0.0, 1.0, 0.0, 0.75]);
+        assert_eq!(green.specular, Material::default().specular);
+        assert_eq!(green.base_colour_texture, None);
+        // The same file is only loaded once, and a missing one is white.
+        assert_eq!(green.normal_texture, Some(0));
+        assert_eq!(model.textures.len(), 1);
+        assert_eq!(model.textures[0].pixels, Texture::white().pixels);
+        // A bump map holds heights, not normals.
+        assert_eq!(model.parts[2].material.normal_texture, None);
+        // Only the files that were found can be watched.
+        assert_eq!(model.files, [dir.join("test.mtl")]);
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let error = load(
+            &Asset::Embedded("test.obj", b"v 0 0 0\nf 1 2 3\n"),
+            &Assets::new(None),
+        )
+        .err()
+        .unwrap();
+
+        assert!(matches!(error, MeshError::Parse(_, 2, _)));
+    }
+}
@@ -0,0 +1,2337 @@
+use crate::assets::{Asset, Assets};
+use crate::environment::{self, Cubemap, EnvironmentMaps, Equirect};
+use crate::error::Error;
+use crate::settings::{DrawMode, Levels, Tonemapper};
+use crate::shaders::{
+    cluster_cs, exposure_cs, fs, fullscreen_vs, histogram_cs, shadow_vs, sky_fs, sky_vs,
+    tonemap_fs, vs, MonkeInstance, Vertex,
+};
+use crate::watch;
+
+use crate::mesh::{Material, Model, Texture};
+use crate::scene::{self, CameraStart, Ground, Light, Scene, SceneError, Swarm};
+
+use bytemuck::Zeroable;
+use half::f16;
+use math::{deg_2_rad, perspective_rh, Aabb, Mat3, Mat4, Vec3, Vec4, FAR, NEAR};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+use vulkano::{
+    buffer::{
+        cpu_pool::CpuBufferPoolChunk, BufferContents, BufferUsage, CpuAccessibleBuffer,
+        CpuBufferPool, DeviceLocalBuffer, TypedBufferAccess,
+    },
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BufferImageCopy,
+        CommandBufferUsage, CopyBufferToImageInfo, PrimaryAutoCommandBuffer,
+        PrimaryCommandBufferAbstract, RenderPassBeginInfo, SubpassContents,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::{
+        physical::{PhysicalDevice, PhysicalDeviceType},
+        Device, DeviceExtensions, DeviceOwned, Features, Queue,
+    },
+    format::{ClearValue, Format, NumericType},
+    image::{
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+        AttachmentImage, ImageAccess, ImageCreateFlags, ImageDimensions, ImageFormatInfo,
+        ImageLayout, ImageSubresourceLayers, ImageUsage, ImageViewAbstract, ImmutableImage,
+        MipmapsCount, SampleCount, SampleCounts,
+    },
+    instance::Instance,
+    memory::allocator::{MemoryUsage, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::{DepthBias, DepthBiasState, PolygonMode, RasterizationState},
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreationError,
+        },
+        ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint, StateMode,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    sampler::{BorderColor, Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    shader::ShaderModule,
+    swapchain::{ColorSpace, Surface},
+    sync::GpuFuture,
+};
+
+/// Owns everything needed to draw the scene, no matter if the frames end up in a swapchain or in
+/// an offscreen image.
+pub struct Renderer {
+    /// World space bounds of every instance, before any animation moves them.
+    pub bounds: Aabb,
+    /// The camera of the scene file, followed by the ones in its models.
+    pub cameras: Vec<CameraStart>,
+    /// The files on disk the models of the scene read besides the mesh files, see `Model::files`.
+    pub model_files: Vec<PathBuf>,
+    pub memory_allocator: Arc<StandardMemoryAllocator>,
+    pub descriptor_set_allocator: StandardDescriptorSetAllocator,
+    pub command_buffer_allocator: StandardCommandBufferAllocator,
+    /// Where the textures are uploaded.
+    queue: Arc<Queue>,
+    vs: Arc<ShaderModule>,
+    fs: Arc<ShaderModule>,
+    vs_uniform_buffer: CpuBufferPool<vs::ty::Data>,
+    fs_uniform_buffer: CpuBufferPool<fs::ty::Data>,
+    /// Instances are animated, so they are uploaded again every frame.
+    instance_buffer: CpuBufferPool<MonkeInstance>,
+    meshes: Vec<SceneMesh>,
+    /// Repeats and blends between the mipmaps.
+    texture_sampler: Arc<Sampler>,
+    /// For everything without a texture of its own.
+    white_texture: Arc<ImageView<ImmutableImage>>,
+    /// For everything without a normal map of its own.
+    flat_normal_map: Arc<ImageView<ImmutableImage>>,
+    /// Tilts the normals with the normal maps on the textured level.
+    pub normal_mapping: bool,
+    /// What the scene's environment maps are made from, and the image to make them from.
+    wanted_environment: Option<(EnvironmentSource, Asset)>,
+    /// The maps `prepare` made last, drawn and lit with from level 11 on as long as the scene
+    /// wants the same ones. Kept across scene loads.
+    environment: Option<(EnvironmentSource, SceneEnvironment)>,
+    /// Bound in place of the environment maps when there are none.
+    black_cubemap: Arc<ImageView<ImmutableImage>>,
+    /// Scale and bias for the Fresnel term of the reflections, see `environment::brdf_lut`.
+    /// Uploaded along with the first environment.
+    brdf_lut: Option<Arc<ImageView<ImmutableImage>>>,
+    /// Blends between the mipmaps of the cube maps and never repeats.
+    environment_sampler: Arc<Sampler>,
+    sky_vs: Arc<ShaderModule>,
+    sky_fs: Arc<ShaderModule>,
+    /// Draws the sky into `render_pass_1`, with the same samples. Only there when that is.
+    sky_pipeline_1: Option<Arc<GraphicsPipeline>>,
+    sky_pipeline_2: Arc<GraphicsPipeline>,
+    clear_colour: [f32; 4],
+    /// From the scene file, moved around with `move_light`.
+    pub lights: Vec<Light>,
+    /// Uploaded every frame, since the lights can move.
+    light_buffer: CpuBufferPool<fs::ty::Light>,
+    /// Drawn at every point and spot light.
+    gizmo_vertices: Arc<CpuAccessibleBuffer<[Vertex]>>,
+    gizmo_indices: Arc<CpuAccessibleBuffer<[u32]>>,
+    /// The swarm of the scene, its lights move with the animations.
+    swarm: Option<Swarm>,
+    /// Lists the lights that reach into every cluster, from level 9 on.
+    cluster_pipeline: Arc<ComputePipeline>,
+    cluster_uniform_buffer: CpuBufferPool<cluster_cs::ty::Data>,
+    /// How many lights every cluster lists.
+    cluster_light_counts: Arc<DeviceLocalBuffer<[u32]>>,
+    /// `MAX_LIGHTS_PER_CLUSTER` indices into the lights for every cluster.
+    cluster_light_indices: Arc<DeviceLocalBuffer<[u32]>>,
+    /// Shows how many lights every cluster lists instead of the lighting, on level 9.
+    pub cluster_heatmap: bool,
+    /// Tonemaps the HDR target into the image.
+    output_render_pass: Arc<RenderPass>,
+    output_pipeline: Arc<GraphicsPipeline>,
+    /// The image isn't sRGB, so the output pass encodes the colour itself.
+    encode_srgb: bool,
+    /// Shows the left half of the screen without the sRGB encoding, to compare.
+    pub gamma_comparison: bool,
+    /// How the HDR colour is squeezed into the image from level 12 on.
+    pub tonemapper: Tonemapper,
+    /// In stops, from level 12 on. On top of the auto exposure when that is on.
+    pub exposure: f32,
+    /// Exposes for the average luminance of the scene, from level 12 on.
+    pub auto_exposure: bool,
+    /// Seconds since the last frame, which is how long the auto exposure had to adapt.
+    pub frame_time: f32,
+    histogram_pipeline: Arc<ComputePipeline>,
+    exposure_pipeline: Arc<ComputePipeline>,
+    /// Pixels by log luminance, see `histogram.comp`. Host visible only so that they start out
+    /// zeroed, `exposure.comp` empties them again every frame.
+    luminance_histogram: Arc<CpuAccessibleBuffer<[u32]>>,
+    /// What the auto exposure has adapted to, 0 before the first frame.
+    average_luminance: Arc<CpuAccessibleBuffer<f32>>,
+    /// Samples per pixel from level 4 on. `Sample1` when the device can't multisample.
+    pub samples: SampleCount,
+    /// The counts out of `MSAA_SAMPLE_COUNTS` that the device supports.
+    sample_counts: Vec<SampleCount>,
+    /// Multisampled and resolving into the HDR target. Only there when `samples` is more than one.
+    render_pass_1: Option<Arc<RenderPass>>,
+    render_pass_2: Arc<RenderPass>,
+    /// Depth seen from the first directional light, rendered from level 6 on.
+    shadow_map: Arc<ImageView<AttachmentImage>>,
+    shadow_sampler: Arc<Sampler>,
+    shadow_framebuffer: Arc<Framebuffer>,
+    shadow_pipeline: Arc<GraphicsPipeline>,
+    /// World space to the clip space of the shadow map.
+    light_space: Mat4,
+}
+
+/// Distance from a light gizmo to its corners.
+const GIZMO_SIZE: f32 = 0.1;
+
+/// Clusters across, down and in depth. `cluster.comp` and `shader.frag` have the same numbers, and
+/// the workgroup size of `cluster.comp` is the first two.
+const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+/// Has to match `cluster.comp` and `shader.frag` too.
+const MAX_LIGHTS_PER_CLUSTER: u32 = 128;
+
+/// Colours are stored in sRGB, sampling turns them linear.
+const COLOUR_TEXTURE_FORMAT: Format = Format::R8G8B8A8_SRGB;
+/// Normal maps hold directions, which are read as they are.
+const NORMAL_MAP_FORMAT: Format = Format::R8G8B8A8_UNORM;
+/// Environments are brighter than 1 in places, the sun most of all.
+const ENVIRONMENT_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+const BRDF_LUT_FORMAT: Format = Format::R16G16_SFLOAT;
+
+/// The scene is drawn in this format and tonemapped into the image.
+const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+/// Width and height of the workgroups of `histogram.comp`.
+const HISTOGRAM_WORKGROUP_SIZE: u32 = 16;
+/// One for every invocation of `exposure.comp`.
+const HISTOGRAM_BINS: u32 = 256;
+/// How quickly the auto exposure adapts to a change in brightness, per second.
+const EXPOSURE_ADAPTATION_RATE: f32 = 1.5;
+
+/// Width and height of the shadow map.
+const SHADOW_MAP_SIZE: u32 = 2048;
+const SHADOW_MAP_FORMAT: Format = Format::D16_UNORM;
+
+/// What `next_samples` cycles through, as far as the device supports them.
+const MSAA_SAMPLE_COUNTS: [SampleCount; 4] = [
+    SampleCount::Sample2,
+    SampleCount::Sample4,
+    SampleCount::Sample8,
+    SampleCount::Sample16,
+];
+
+/// What `window_size_dependent_setup` builds: the pipelines of the draw mode in drawing order and
+/// a target for every image.
+pub type SizeDependent = (Vec<Arc<GraphicsPipeline>>, Vec<FrameTarget>);
+
+/// A texture uploaded once for every format it is used in.
+type UploadedTextures<K> = HashMap<(K, Format), Arc<ImageView<ImmutableImage>>>;
+
+/// Where a frame is drawn: the scene into the HDR target, which is then tonemapped into the image.
+#[derive(Clone)]
+pub struct FrameTarget {
+    scene: Arc<Framebuffer>,
+    hdr: Arc<ImageView<AttachmentImage>>,
+    output: Arc<Framebuffer>,
+}
+
+/// One draw of the whole scene. Every draw mode is made of one or two of them.
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Pass {
+    Solid,
+    Lines,
+    /// Lines pulled towards the camera so that they win the depth test against the solid pass
+    /// they are drawn over.
+    Edges,
+}
+
+impl Pass {
+    fn for_mode(mode: DrawMode) -> &'static [Pass] {
+        match mode {
+            DrawMode::Solid => &[Pass::Solid],
+            DrawMode::Wireframe => &[Pass::Lines],
+            DrawMode::Overlay => &[Pass::Solid, Pass::Edges],
+        }
+    }
+}
+
+/// What environment maps are made from. They are only made again when it changes.
+#[derive(Clone, PartialEq)]
+struct EnvironmentSource {
+    path: PathBuf,
+    /// `None` for a built-in image, which can't change.
+    modified: Option<SystemTime>,
+    intensity: f32,
+}
+
+/// The environment maps of a scene, on the GPU.
+struct SceneEnvironment {
+    sky: Arc<ImageView<ImmutableImage>>,
+    irradiance: Arc<ImageView<ImmutableImage>>,
+    prefiltered: Arc<ImageView<ImmutableImage>>,
+}
+
+/// One part of a loaded model together with every instance that is drawn with it.
+struct SceneMesh {
+    vertex_buffer: Arc<CpuAccessibleBuffer<[Vertex]>>,
+    index_buffer: Arc<CpuAccessibleBuffer<[u32]>>,
+    /// Where the part sits in its model.
+    transform: Mat4,
+    material: Material,
+    /// The base colour texture of the material, or the one the instances swap in.
+    texture: Arc<ImageView<ImmutableImage>>,
+    /// The same for the normal map.
+    normal_map: Arc<ImageView<ImmutableImage>>,
+    /// The metallic-roughness and specular textures of the material, white without them.
+    metallic_roughness: Arc<ImageView<ImmutableImage>>,
+    specular_map: Arc<ImageView<ImmutableImage>>,
+    instances: Vec<scene::Instance>,
+    /// Only drawn from the shadow level on, and casts no shadow of its own.
+    ground: bool,
+}
+
+impl Renderer {
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        image_format: Format,
+        scene: &Scene,
+        assets: &Assets,
+    ) -> Result<Self, Error> {
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+
+        let vs_uniform_buffer = CpuBufferPool::<vs::ty::Data>::new(
+            memory_allocator.clone(),
+            BufferUsage {
+                uniform_buffer: true,
+                ..BufferUsage::empty()
+            },
+            MemoryUsage::Upload,
+        );
+
+        let fs_uniform_buffer = CpuBufferPool::<fs::ty::Data>::new(
+            memory_allocator.clone(),
+            BufferUsage {
+                uniform_buffer: true,
+                ..BufferUsage::empty()
+            },
+            MemoryUsage::Upload,
+        );
+
+        let instance_buffer = CpuBufferPool::<MonkeInstance>::new(
+            memory_allocator.clone(),
+            BufferUsage {
+                vertex_buffer: true,
+                ..BufferUsage::empty()
+            },
+            MemoryUsage::Upload,
+        );
+
+        let light_buffer = CpuBufferPool::<fs::ty::Light>::new(
+            memory_allocator.clone(),
+            BufferUsage {
+                storage_buffer: true,
+                ..BufferUsage::empty()
+            },
+            MemoryUsage::Upload,
+        );
+
+        let (vertices, indices) = gizmo_mesh();
+        let gizmo_vertices = CpuAccessibleBuffer::from_iter(
+            &*memory_allocator,
+            BufferUsage {
+                vertex_buffer: true,
+                ..BufferUsage::empty()
+            },
+            false,
+            vertices,
+        )
+        .map_err(Error::Buffer)?;
+        let gizmo_indices = CpuAccessibleBuffer::from_iter(
+            &*memory_allocator,
+            BufferUsage {
+                index_buffer: true,
+                ..BufferUsage::empty()
+            },
+            false,
+            indices,
+        )
+        .map_err(Error::Buffer)?;
+
+        let vs = vs::load(device.clone()).map_err(Error::BuiltInShader)?;
+        let fs = fs::load(device.clone()).map_err(Error::BuiltInShader)?;
+
+        // Both the colour and the depth attachment have to support the count, in general and in
+        // the formats they are created in.
+        let properties = device.physical_device().properties();
+        let supported = properties
+            .framebuffer_color_sample_counts
+            .intersection(&properties.framebuffer_depth_sample_counts)
+            .intersection(&attachment_sample_counts(&device, HDR_FORMAT)?)
+            .intersection(&attachment_sample_counts(&device, Format::D16_UNORM)?);
+        let sample_counts: Vec<SampleCount> = MSAA_SAMPLE_COUNTS
+            .into_iter()
+            .filter(|&samples| supported.contains_count(samples))
+            .collect();
+        let samples = sample_counts
+            .last()
+            .copied()
+            .unwrap_or(SampleCount::Sample1);
+        let render_pass_1 = multisampled_render_pass(device.clone(), samples)?;
+
+        let render_pass_2 = vulkano::single_pass_renderpass!(device.clone(),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: HDR_FORMAT,
+                    samples: 1,
+                },
+                depth: {
+                    load: Clear,
+                    store: DontCare,
+                    format: Format::D16_UNORM,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {depth}
+            }
+        )
+        .map_err(Error::RenderPass)?;
+
+        // Every pixel is written, what was there before doesn't matter.
+        let output_render_pass = vulkano::single_pass_renderpass!(device.clone(),
+            attachments: {
+                color: {
+                    load: DontCare,
+                    store: Store,
+                    format: image_format,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {}
+            }
+        )
+        .map_err(Error::RenderPass)?;
+        let fullscreen_vs = fullscreen_vs::load(device.clone()).map_err(Error::BuiltInShader)?;
+        let tonemap_fs = tonemap_fs::load(device.clone()).map_err(Error::BuiltInShader)?;
+        let output_pipeline =
+            output_pipeline(&fullscreen_vs, &tonemap_fs, output_render_pass.clone())
+                .map_err(Error::Pipeline)?;
+
+        let shadow_render_pass = vulkano::single_pass_renderpass!(device.clone(),
+            attachments: {
+                depth: {
+                    load: Clear,
+                    store: Store,
+                    format: SHADOW_MAP_FORMAT,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [],
+                depth_stencil: {depth}
+            }
+        )
+        .map_err(Error::RenderPass)?;
+        let shadow_map = ImageView::new_default(
+            AttachmentImage::sampled(&*memory_allocator, [SHADOW_MAP_SIZE; 2], SHADOW_MAP_FORMAT)
+                .map_err(Error::Image)?,
+        )
+        .map_err(Error::ImageView)?;
+        let shadow_framebuffer = Framebuffer::new(
+            shadow_render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![shadow_map.clone()],
+                ..Default::default()
+            },
+        )
+        .map_err(Error::Framebuffer)?;
+        // Every lookup compares against the stored depth. Outside the map everything is lit.
+        let shadow_sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToBorder; 3],
+                border_color: BorderColor::FloatOpaqueWhite,
+                compare: Some(CompareOp::LessOrEqual),
+                ..Default::default()
+            },
+        )
+        .map_err(Error::Sampler)?;
+        let shadow_vs = shadow_vs::load(device.clone()).map_err(Error::BuiltInShader)?;
+        let shadow_pipeline = shadow_pipeline(device.clone(), &shadow_vs, shadow_render_pass)
+            .map_err(Error::Pipeline)?;
+
+        let cluster_cs = cluster_cs::load(device.clone()).map_err(Error::BuiltInShader)?;
+        let cluster_pipeline = ComputePipeline::new(
+            device.clone(),
+            cluster_cs.entry_point("main").unwrap(),
+            &(),
+            None,
+            |_| {},
+        )
+        .map_err(Error::ComputePipeline)?;
+        let cluster_uniform_buffer = CpuBufferPool::<cluster_cs::ty::Data>::new(
+            memory_allocator.clone(),
+            BufferUsage {
+                uniform_buffer: true,
+                ..BufferUsage::empty()
+            },
+            MemoryUsage::Upload,
+        );
+        let histogram_cs = histogram_cs::load(device.clone()).map_err(Error::BuiltInShader)?;
+        let histogram_pipeline = ComputePipeline::new(
+            device.clone(),
+            histogram_cs.entry_point("main").unwrap(),
+            &(),
+            None,
+            |_| {},
+        )
+        .map_err(Error::ComputePipeline)?;
+        let exposure_cs = exposure_cs::load(device.clone()).map_err(Error::BuiltInShader)?;
+        let exposure_pipeline = ComputePipeline::new(
+            device.clone(),
+            exposure_cs.entry_point("main").unwrap(),
+            &(),
+            None,
+            |_| {},
+        )
+        .map_err(Error::ComputePipeline)?;
+        let luminance_histogram = CpuAccessibleBuffer::from_iter(
+            &*memory_allocator,
+            BufferUsage {
+                storage_buffer: true,
+                ..BufferUsage::empty()
+            },
+            false,
+            (0..HISTOGRAM_BINS).map(|_| 0u32),
+        )
+        .map_err(Error::Buffer)?;
+        let average_luminance = CpuAccessibleBuffer::from_data(
+            &*memory_allocator,
+            BufferUsage {
+                storage_buffer: true,
+                ..BufferUsage::empty()
+            },
+            false,
+            0.0f32,
+        )
+        .map_err(Error::Buffer)?;
+
+        let cluster_count = CLUSTER_GRID.iter().product::<u32>() as u64;
+        let storage = BufferUsage {
+            storage_buffer: true,
+            ..BufferUsage::empty()
+        };
+        let cluster_light_counts =
+            DeviceLocalBuffer::<[u32]>::array(&*memory_allocator, cluster_count, storage, [])
+                .map_err(Error::Buffer)?;
+        let cluster_light_indices = DeviceLocalBuffer::<[u32]>::array(
+            &*memory_allocator,
+            cluster_count * MAX_LIGHTS_PER_CLUSTER as u64,
+            storage,
+            [],
+        )
+        .map_err(Error::Buffer)?;
+
+        let descriptor_set_allocator = StandardDescriptorSetAllocator::new(device.clone());
+        let command_buffer_allocator =
+            StandardCommandBufferAllocator::new(device.clone(), Default::default());
+
+        let texture_sampler =
+            Sampler::new(device.clone(), SamplerCreateInfo::simple_repeat_linear())
+                .map_err(Error::Sampler)?;
+        let mut defaults = upload_textures(
+            &memory_allocator,
+            &command_buffer_allocator,
+            &queue,
+            &[
+                (&Texture::white(), COLOUR_TEXTURE_FORMAT),
+                (&Texture::flat_normal(), NORMAL_MAP_FORMAT),
+            ],
+        )?;
+        let flat_normal_map = defaults.pop().unwrap();
+        let white_texture = defaults.pop().unwrap();
+
+        let environment_sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..SamplerCreateInfo::simple_repeat_linear()
+            },
+        )
+        .map_err(Error::Sampler)?;
+        let black_cubemap = run_uploads(&command_buffer_allocator, &queue, |builder| {
+            upload_cubemap(&memory_allocator, &queue, builder, &Cubemap::black())
+        })?;
+        let sky_vs = sky_vs::load(device.clone()).map_err(Error::BuiltInShader)?;
+        let sky_fs = sky_fs::load(device.clone()).map_err(Error::BuiltInShader)?;
+        let sky_pipeline_1 = render_pass_1
+            .as_ref()
+            .map(|render_pass| sky_pipeline(&sky_vs, &sky_fs, render_pass.clone(), samples))
+            .transpose()
+            .map_err(Error::Pipeline)?;
+        let sky_pipeline_2 = sky_pipeline(
+            &sky_vs,
+            &sky_fs,
+            render_pass_2.clone(),
+            SampleCount::Sample1,
+        )
+        .map_err(Error::Pipeline)?;
+
+        let mut renderer = Self {
+            bounds: Aabb::new(Vec3::ZERO, Vec3::ZERO),
+            cameras: vec![],
+            model_files: vec![],
+            memory_allocator,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            queue,
+            vs,
+            fs,
+            vs_uniform_buffer,
+            fs_uniform_buffer,
+            instance_buffer,
+            meshes: vec![],
+            texture_sampler,
+            white_texture,
+            flat_normal_map,
+            normal_mapping: true,
+            wanted_environment: None,
+            environment: None,
+            black_cubemap,
+            brdf_lut: None,
+            environment_sampler,
+            sky_vs,
+            sky_fs,
+            sky_pipeline_1,
+            sky_pipeline_2,
+            clear_colour: [0.0; 4],
+            lights: vec![],
+            light_buffer,
+            gizmo_vertices,
+            gizmo_indices,
+            swarm: None,
+            cluster_pipeline,
+            cluster_uniform_buffer,
+            cluster_light_counts,
+            cluster_light_indices,
+            cluster_heatmap: false,
+            output_render_pass,
+            output_pipeline,
+            encode_srgb: image_format.type_color() != Some(NumericType::SRGB),
+            gamma_comparison: false,
+            tonemapper: Tonemapper::Aces,
+            exposure: 0.0,
+            auto_exposure: true,
+            frame_time: 0.0,
+            histogram_pipeline,
+            exposure_pipeline,
+            luminance_histogram,
+            average_luminance,
+            samples,
+            sample_counts,
+            render_pass_1,
+            render_pass_2,
+            shadow_map,
+            shadow_sampler,
+            shadow_framebuffer,
+            shadow_pipeline,
+            light_space: Mat4::IDENTITY,
+        };
+        renderer.load_scene(scene, assets)?;
+        Ok(renderer)
+    }
+
+    /// Loads every model of `scene` and uploads its parts with their instances. Models are drawn in
+    /// the order they are first used by an instance. The files the models refer to are found
+    /// through `assets`.
+    pub fn load_scene(&mut self, scene: &Scene, assets: &Assets) -> Result<(), Error> {
+        let mut names: Vec<&String> = vec![];
+        for instance in &scene.instances {
+            if !names.contains(&&instance.mesh) {
+                names.push(&instance.mesh);
+            }
+        }
+
+        let decoded = scene
+            .textures
+            .iter()
+            .map(|(name, asset)| {
+                Texture::load(asset)
+                    .map(|texture| (name, texture))
+                    .map_err(|e| SceneError::Texture(asset.path().to_path_buf(), e))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()
+            .map_err(Error::Scene)?;
+        let scene_uses: HashSet<(&String, Format)> = scene
+            .instances
+            .iter()
+            .flat_map(|instance| {
+                let colour = instance
+                    .texture
+                    .iter()
+                    .map(|name| (name, COLOUR_TEXTURE_FORMAT));
+                let normal = instance
+                    .normal_map
+                    .iter()
+                    .map(|name| (name, NORMAL_MAP_FORMAT));
+                colour.chain(normal)
+            })
+            .collect();
+        let scene_textures = self.upload_used(scene_uses, |name| &decoded[name])?;
+
+        // Only made into maps once a level that draws them comes up, see `prepare`.
+        let wanted_environment = scene.environment.as_ref().and_then(|environment| {
+            let image = environment.image.clone()?;
+            let source = EnvironmentSource {
+                path: image.path().to_path_buf(),
+                modified: image.file().and_then(watch::modified),
+                intensity: environment.intensity,
+            };
+            Some((source, image))
+        });
+
+        let mut meshes = vec![];
+        let mut bounds: Option<Aabb> = None;
+        let mut cameras = vec![scene.camera.clone()];
+        let mut model_files = vec![];
+        for name in names {
+            let asset = &scene.meshes[name];
+            let model = Model::load(asset, assets)
+                .map_err(|e| Error::Scene(SceneError::Mesh(asset.path().to_path_buf(), e)))?;
+            let model_uses: HashSet<(usize, Format)> = model
+                .parts
+                .iter()
+                .flat_map(|part| {
+                    let material = &part.material;
+                    let colour = material
+                        .base_colour_texture
+                        .map(|index| (index, COLOUR_TEXTURE_FORMAT));
+                    let normal = material
+                        .normal_texture
+                        .map(|index| (index, NORMAL_MAP_FORMAT));
+                    // Not a colour, so read as it is stored.
+                    let metallic_roughness = material
+                        .metallic_roughness_texture
+                        .map(|index| (index, NORMAL_MAP_FORMAT));
+                    let specular = material
+                        .specular_texture
+                        .map(|index| (index, COLOUR_TEXTURE_FORMAT));
+                    colour
+                        .into_iter()
+                        .chain(normal)
+                        .chain(metallic_roughness)
+                        .chain(specular)
+                })
+                .filter(|&(index, _)| index < model.textures.len())
+                .collect();
+            let model_textures = self.upload_used(model_uses, |index| &model.textures[index])?;
+            model_files.extend(model.files.iter().cloned());
+            let instances: Vec<scene::Instance> = scene
+                .instances
+                .iter()
+                .filter(|instance| &instance.mesh == name)
+                .cloned()
+                .collect();
+
+            for instance in &instances {
+                if let Some(part) = &instance.part {
+                    if !model
+                        .parts
+                        .iter()
+                        .any(|model_part| &model_part.name == part)
+                    {
+                        return Err(Error::Scene(SceneError::UnknownPart(
+                            name.clone(),
+                            part.clone(),
+                        )));
+                    }
+                }
+                let instance_matrix = instance.location_at(0.0).model_matrix();
+                cameras.extend(
+                    model
+                        .cameras
+                        .iter()
+                        .map(|&camera| CameraStart::from_transform(instance_matrix * camera)),
+                );
+            }
+
+            for part in model.parts {
+                let instances: Vec<scene::Instance> = instances
+                    .iter()
+                    .filter(|instance| instance.part.as_ref().is_none_or(|p| *p == part.name))
+                    .cloned()
+                    .collect();
+                let mesh = part.mesh;
+                let mesh_bounds =
+                    match Aabb::from_points(mesh.vertices.iter().map(|v| Vec3::from(v.position))) {
+                        Some(mesh_bounds) if !mesh.indices.is_empty() && !instances.is_empty() => {
+                            mesh_bounds
+                        }
+                        _ => continue,
+                    };
+
+                for instance in &instances {
+                    let instance_bounds = mesh_bounds
+                        .transformed(instance.location_at(0.0).model_matrix() * part.transform);
+                    bounds = Some(match bounds {
+                        Some(bounds) => bounds.union(&instance_bounds),
+                        None => instance_bounds,
+                    });
+                }
+
+                let vertex_buffer = self.upload(
+                    BufferUsage {
+                        vertex_buffer: true,
+                        ..BufferUsage::empty()
+                    },
+                    mesh.vertices,
+                )?;
+                let index_buffer = self.upload(
+                    BufferUsage {
+                        index_buffer: true,
+                        ..BufferUsage::empty()
+                    },
+                    mesh.indices,
+                )?;
+                // The texture an instance swaps in, or else the one of the material, or else the
+                // default.
+                let pick = |instance: Option<&String>, material: Option<usize>, format, default| {
+                    let picked = match instance {
+                        Some(name) => scene_textures.get(&(name, format)),
+                        None => material.and_then(|index| model_textures.get(&(index, format))),
+                    };
+                    Arc::clone(picked.unwrap_or(default))
+                };
+
+                // Instances that swap in other textures are drawn separately, the buffers are
+                // shared.
+                let mut by_textures: Vec<(Option<String>, Option<String>, Vec<scene::Instance>)> =
+                    vec![];
+                for instance in instances {
+                    match by_textures.iter_mut().find(|(texture, normal_map, _)| {
+                        *texture == instance.texture && *normal_map == instance.normal_map
+                    }) {
+                        Some((_, _, instances)) => instances.push(instance),
+                        None => by_textures.push((
+                            instance.texture.clone(),
+                            instance.normal_map.clone(),
+                            vec![instance],
+                        )),
+                    }
+                }
+                for (texture, normal_map, instances) in by_textures {
+                    let material = &part.material;
+                    meshes.push(SceneMesh {
+                        vertex_buffer: vertex_buffer.clone(),
+                        index_buffer: index_buffer.clone(),
+                        transform: part.transform,
+                        material: material.clone(),
+                        texture: pick(
+                            texture.as_ref(),
+                            material.base_colour_texture,
+                            COLOUR_TEXTURE_FORMAT,
+                            &self.white_texture,
+                        ),
+                        normal_map: pick(
+                            normal_map.as_ref(),
+                            material.normal_texture,
+                            NORMAL_MAP_FORMAT,
+                            &self.flat_normal_map,
+                        ),
+                        metallic_roughness: pick(
+                            None,
+                            material.metallic_roughness_texture,
+                            NORMAL_MAP_FORMAT,
+                            &self.white_texture,
+                        ),
+                        specular_map: pick(
+                            None,
+                            material.specular_texture,
+                            COLOUR_TEXTURE_FORMAT,
+                            &self.white_texture,
+                        ),
+                        instances,
+                        ground: false,
+                    });
+                }
+            }
+        }
+
+        let bounds = bounds.unwrap_or_else(|| Aabb::new(Vec3::ZERO, Vec3::ZERO));
+        if let Some(ground) = &scene.ground {
+            meshes.push(self.ground_mesh(ground, bounds)?);
+        }
+
+        self.meshes = meshes;
+        self.cameras = cameras;
+        self.model_files = model_files;
+        self.bounds = bounds;
+        self.clear_colour = scene.clear_colour;
+        self.lights = scene.lights.clone();
+        self.swarm = scene.swarm.clone();
+        self.wanted_environment = wanted_environment;
+        self.update_light_space();
+        Ok(())
+    }
+
+    /// Makes the maps of the scene's environment and uploads them if `level` draws them and they
+    /// aren't there yet, along with the BRDF lookup table the first time. Maps made from the same
+    /// image and intensity before are reused. Call it before `draw`.
+    pub fn prepare(&mut self, level: Levels) -> Result<(), Error> {
+        if level < Levels::ELEVEN || self.current_environment().is_some() {
+            return Ok(());
+        }
+        // Put back only once it worked, so that a broken image isn't read again every frame.
+        let (source, image) = match self.wanted_environment.take() {
+            Some(wanted) => wanted,
+            None => return Ok(()),
+        };
+        let equirect = Equirect::load(&image)
+            .map_err(|e| Error::Scene(SceneError::Environment(image.path().to_path_buf(), e)))?;
+        let maps = self.upload_environment(&EnvironmentMaps::new(&equirect, source.intensity))?;
+        if self.brdf_lut.is_none() {
+            self.brdf_lut = Some(upload_brdf_lut(
+                &self.memory_allocator,
+                &self.command_buffer_allocator,
+                &self.queue,
+            )?);
+        }
+        self.environment = Some((source.clone(), maps));
+        self.wanted_environment = Some((source, image));
+        Ok(())
+    }
+
+    /// The maps of the scene's environment, if `prepare` made them.
+    fn current_environment(&self) -> Option<&SceneEnvironment> {
+        let (wanted, _) = self.wanted_environment.as_ref()?;
+        let (source, maps) = self.environment.as_ref()?;
+        (source == wanted).then_some(maps)
+    }
+
+    /// Moves the light at `index` by `offset`, see `Light::move_by`.
+    pub fn move_light(&mut self, index: usize, offset: Vec3) {
+        if let Some(light) = self.lights.get_mut(index) {
+            light.move_by(offset);
+            self.update_light_space();
+        }
+    }
+
+    /// The first directional light, which is the one that casts the shadows.
+    fn shadow_light(&self) -> Option<(usize, Vec3)> {
+        self.lights
+            .iter()
+            .enumerate()
+            .find_map(|(index, light)| match *light {
+                Light::Directional { direction, .. } => Some((index, Vec3::from(direction))),
+                _ => None,
+            })
+    }
+
+    fn update_light_space(&mut self) {
+        // Without a light to cast them there are no shadows, so any direction will do.
+        let direction = self
+            .shadow_light()
+            .map_or(Vec3::Y, |(_, direction)| direction);
+        self.light_space = light_space(direction, self.bounds);
+    }
+
+    /// A square under `bounds`, reaching well past them on every side.
+    fn ground_mesh(&self, ground: &Ground, bounds: Aabb) -> Result<SceneMesh, Error> {
+        let height = ground.height.unwrap_or(bounds.min.y);
+        let center = bounds.center();
+        let half_size = bounds.radius().max(1.0) * 2.0;
+        let vertices = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .map(|(x, z)| Vertex {
+                position: [center.x + x * half_size, height, center.z + z * half_size],
+                normal: [0.0, 1.0, 0.0],
+                uv: [x * 0.5 + 0.5, z * 0.5 + 0.5],
+                // Along u, with the bitangent going up the image, towards -z.
+                tangent: [1.0, 0.0, 0.0, 1.0],
+            })
+            .to_vec();
+        Ok(SceneMesh {
+            vertex_buffer: self.upload(
+                BufferUsage {
+                    vertex_buffer: true,
+                    ..BufferUsage::empty()
+                },
+                vertices,
+            )?,
+            index_buffer: self.upload(
+                BufferUsage {
+                    index_buffer: true,
+                    ..BufferUsage::empty()
+                },
+                vec![0, 1, 2, 0, 2, 3],
+            )?,
+            transform: Mat4::IDENTITY,
+            material: Material::default(),
+            texture: self.white_texture.clone(),
+            normal_map: self.flat_normal_map.clone(),
+            metallic_roughness: self.white_texture.clone(),
+            specular_map: self.white_texture.clone(),
+            instances: vec![scene::Instance {
+                mesh: String::new(),
+                position: [0.0; 3],
+                rotation: [0.0; 3],
+                scale: scene::Scale::default(),
+                colour: ground.colour,
+                // Matte, so the highlights stay on the monkeys.
+                specular: Some([0.0; 3]),
+                shininess: None,
+                metallic: None,
+                roughness: Some(1.0),
+                texture: None,
+                normal_map: None,
+                part: None,
+                animation: None,
+            }],
+            ground: true,
+        })
+    }
+
+    fn upload<T>(
+        &self,
+        usage: BufferUsage,
+        data: Vec<T>,
+    ) -> Result<Arc<CpuAccessibleBuffer<[T]>>, Error>
+    where
+        [T]: BufferContents,
+    {
+        CpuAccessibleBuffer::from_iter(&self.memory_allocator, usage, false, data)
+            .map_err(Error::Buffer)
+    }
+
+    /// Uploads every texture that `uses` asks for, once for every format it is used in.
+    fn upload_used<'a, K>(
+        &self,
+        uses: HashSet<(K, Format)>,
+        texture: impl Fn(K) -> &'a Texture,
+    ) -> Result<UploadedTextures<K>, Error>
+    where
+        K: Copy + Eq + Hash,
+    {
+        let uses: Vec<(K, Format)> = uses.into_iter().collect();
+        let textures: Vec<(&Texture, Format)> = uses
+            .iter()
+            .map(|&(key, format)| (texture(key), format))
+            .collect();
+        let views = upload_textures(
+            &self.memory_allocator,
+            &self.command_buffer_allocator,
+            &self.queue,
+            &textures,
+        )?;
+        Ok(uses.into_iter().zip(views).collect())
+    }
+
+    fn upload_environment(&self, maps: &EnvironmentMaps) -> Result<SceneEnvironment, Error> {
+        let (memory_allocator, queue) = (&*self.memory_allocator, &self.queue);
+        run_uploads(&self.command_buffer_allocator, queue, |builder| {
+            Ok(SceneEnvironment {
+                sky: upload_cubemap(memory_allocator, queue, builder, &maps.sky)?,
+                irradiance: upload_cubemap(memory_allocator, queue, builder, &maps.irradiance)?,
+                prefiltered: upload_cubemap(memory_allocator, queue, builder, &maps.prefiltered)?,
+            })
+        })
+    }
+
+    pub fn render_pass(&self, level: Levels) -> Arc<RenderPass> {
+        match &self.render_pass_1 {
+            Some(render_pass) if level >= Levels::FOUR => render_pass.clone(),
+            _ => self.render_pass_2.clone(),
+        }
+    }
+
+    fn multisampled(&self, level: Levels) -> bool {
+        level >= Levels::FOUR && self.render_pass_1.is_some()
+    }
+
+    /// Switches to the next sample count the device supports and returns it, or `None` if the
+    /// device can't multisample at all. On an error the current count stays. The framebuffers and
+    /// pipelines have to be rebuilt with `window_size_dependent_setup` afterwards.
+    pub fn next_samples(&mut self) -> Result<Option<SampleCount>, Error> {
+        let current = self
+            .sample_counts
+            .iter()
+            .position(|&samples| samples == self.samples);
+        let next = match current {
+            Some(index) => self.sample_counts[(index + 1) % self.sample_counts.len()],
+            None => return Ok(None),
+        };
+        let render_pass_1 = multisampled_render_pass(self.memory_allocator.device().clone(), next)?;
+        self.sky_pipeline_1 = render_pass_1
+            .as_ref()
+            .map(|render_pass| sky_pipeline(&self.sky_vs, &self.sky_fs, render_pass.clone(), next))
+            .transpose()
+            .map_err(Error::Pipeline)?;
+        self.render_pass_1 = render_pass_1;
+        self.samples = next;
+        Ok(Some(next))
+    }
+
+    /// This method is called once during initialization, then again whenever the window is resized
+    pub fn window_size_dependent_setup<I>(
+        &self,
+        images: &[Arc<I>],
+        level: Levels,
+        mode: DrawMode,
+    ) -> Result<SizeDependent, Error>
+    where
+        I: ImageAccess + Debug + 'static,
+    {
+        let memory_allocator = &*self.memory_allocator;
+        let render_pass = self.render_pass(level);
+        let dimensions = images[0].dimensions().width_height();
+
+        let multisampled = self.multisampled(level);
+        let depth_buffer = ImageView::new_default(
+            match multisampled {
+                true => AttachmentImage::transient_multisampled(
+                    memory_allocator,
+                    dimensions,
+                    self.samples,
+                    Format::D16_UNORM,
+                ),
+                false => {
+                    AttachmentImage::transient(memory_allocator, dimensions, Format::D16_UNORM)
+                }
+            }
+            .map_err(Error::Image)?,
+        )
+        .map_err(Error::ImageView)?;
+
+        // Like the depth buffer, the HDR target is shared by every image.
+        let hdr = ImageView::new_default(
+            AttachmentImage::sampled(memory_allocator, dimensions, HDR_FORMAT)
+                .map_err(Error::Image)?,
+        )
+        .map_err(Error::ImageView)?;
+        let attachments: Vec<Arc<dyn ImageViewAbstract>> = if multisampled {
+            let intermediary = ImageView::new_default(
+                AttachmentImage::transient_multisampled(
+                    memory_allocator,
+                    dimensions,
+                    self.samples,
+                    HDR_FORMAT,
+                )
+                .map_err(Error::Image)?,
+            )
+            .map_err(Error::ImageView)?;
+            vec![intermediary, hdr.clone(), depth_buffer]
+        } else {
+            vec![hdr.clone(), depth_buffer]
+        };
+        let scene = Framebuffer::new(
+            render_pass,
+            FramebufferCreateInfo {
+                attachments,
+                ..Default::default()
+            },
+        )
+        .map_err(Error::Framebuffer)?;
+
+        let targets = images
+            .iter()
+            .map(|image| {
+                let view = ImageView::new_default(image.clone()).map_err(Error::ImageView)?;
+                let output = Framebuffer::new(
+                    self.output_render_pass.clone(),
+                    FramebufferCreateInfo {
+                        attachments: vec![view],
+                        ..Default::default()
+                    },
+                )
+                .map_err(Error::Framebuffer)?;
+                Ok(FrameTarget {
+                    scene: scene.clone(),
+                    hdr: hdr.clone(),
+                    output,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let pipelines = self
+            .build_pipelines(&self.vs, &self.fs, dimensions, level, mode)
+            .map_err(Error::Pipeline)?;
+
+        Ok((pipelines, targets))
+    }
+
+    /// Swaps in new shader modules if the pipelines can be built with them. Until then the current
+    /// modules and the pipelines that use them stay in place.
+    pub fn replace_shaders(
+        &mut self,
+        vs: Arc<ShaderModule>,
+        fs: Arc<ShaderModule>,
+        dimensions: [u32; 2],
+        level: Levels,
+        mode: DrawMode,
+    ) -> Result<Vec<Arc<GraphicsPipeline>>, GraphicsPipelineCreationError> {
+        let pipelines = self.build_pipelines(&vs, &fs, dimensions, level, mode)?;
+        self.vs = vs;
+        self.fs = fs;
+        Ok(pipelines)
+    }
+
+    /// One pipeline for every pass of `mode`, in drawing order.
+    fn build_pipelines(
+        &self,
+        vs: &ShaderModule,
+        fs: &ShaderModule,
+        dimensions: [u32; 2],
+        level: Levels,
+        mode: DrawMode,
+    ) -> Result<Vec<Arc<GraphicsPipeline>>, GraphicsPipelineCreationError> {
+        Pass::for_mode(mode)
+            .iter()
+            .map(|&pass| self.build_pipeline(vs, fs, dimensions, level, pass))
+            .collect()
+    }
+
+    fn build_pipeline(
+        &self,
+        vs: &ShaderModule,
+        fs: &ShaderModule,
+        dimensions: [u32; 2],
+        level: Levels,
+        pass: Pass,
+    ) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+        let rasterization_state = match pass {
+            Pass::Solid => RasterizationState::new(),
+            Pass::Lines => RasterizationState::new().polygon_mode(PolygonMode::Line),
+            Pass::Edges => RasterizationState {
+                depth_bias: Some(DepthBiasState {
+                    enable_dynamic: false,
+                    bias: StateMode::Fixed(DepthBias {
+                        constant_factor: -1.0,
+                        clamp: 0.0,
+                        slope_factor: -1.0,
+                    }),
+                }),
+                ..RasterizationState::new().polygon_mode(PolygonMode::Line)
+            },
+        };
+
+        let mut pipeline = GraphicsPipeline::start()
+            .vertex_input_state(
+                BuffersDefinition::new()
+                    .vertex::<Vertex>()
+                    .instance::<MonkeInstance>(),
+            )
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([
+                Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                    depth_range: 0.0..1.0,
+                },
+            ]))
+            .rasterization_state(rasterization_state)
+            .fragment_shader(
+                fs.entry_point("main").unwrap(),
+                fs::SpecializationConstants {
+                    overlay: (pass == Pass::Edges) as u32,
+                },
+            );
+        if level >= Levels::THREE {
+            pipeline = pipeline.depth_stencil_state(DepthStencilState::simple_depth_test());
+        } else {
+            pipeline = pipeline.depth_stencil_state(DepthStencilState::disabled());
+        }
+
+        if self.multisampled(level) {
+            pipeline = pipeline.multisample_state(MultisampleState {
+                rasterization_samples: self.samples,
+                ..Default::default()
+            })
+        }
+        pipeline
+            .render_pass(Subpass::from(self.render_pass(level), 0).unwrap())
+            .build(self.memory_allocator.device().clone())
+    }
+
+    /// Records one full pass over the scene into `target`, with the animations `time`
+    /// seconds in.
+    pub fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        target: FrameTarget,
+        pipelines: &[Arc<GraphicsPipeline>],
+        level: Levels,
+        view: Mat4,
+        time: f32,
+    ) -> Result<(), Error> {
+        let [width, height] = target.output.extent();
+        let aspect_ratio = width as f32 / height as f32;
+        let proj = perspective_rh(aspect_ratio);
+        let vs_uniform_buffer_subbuffer = {
+            let uniform_data = vs::ty::Data {
+                view: view.to_cols_array_2d(),
+                proj,
+                light_space: self.light_space.to_cols_array_2d(),
+                lev_2: (level >= Levels::TWO) as u32,
+            };
+
+            self.vs_uniform_buffer
+                .from_data(uniform_data)
+                .map_err(Error::Buffer)?
+        };
+
+        // The scene lights come first, so the index of the shadow light stays the same.
+        let lights: Vec<Light> = self
+            .lights
+            .iter()
+            .cloned()
+            .chain(self.swarm.iter().flat_map(|swarm| swarm.lights_at(time)))
+            .collect();
+        let shadow_light = self.shadow_light().map(|(index, _)| index);
+        let shadows = level >= Levels::SIX && shadow_light.is_some();
+        let clustered = level >= Levels::NINE;
+        let environment = self
+            .current_environment()
+            .filter(|_| level >= Levels::ELEVEN);
+
+        let inverse_view = view.inverse();
+        let fs_uniform_buffer_subbuffer = {
+            let uniform_data = fs::ty::Data {
+                camera_position: inverse_view.transform_point3(Vec3::ZERO).extend(1.0).into(),
+                camera_forward: inverse_view.transform_vector3(-Vec3::Z).extend(0.0).into(),
+                screen_size: [width as f32, height as f32],
+                light_count: lights.len() as u32,
+                shadow_light: shadow_light.unwrap_or(0) as u32,
+                near: NEAR,
+                far: FAR,
+                lighting: (level >= Levels::FIVE) as u32,
+                shadows: shadows as u32,
+                specular: (level >= Levels::SEVEN) as u32,
+                physically_based: (level >= Levels::EIGHT) as u32,
+                clustered: clustered as u32,
+                textured: (level >= Levels::TEN) as u32,
+                normal_mapping: (level >= Levels::TEN && self.normal_mapping) as u32,
+                image_based: environment.is_some() as u32,
+                cluster_heatmap: (clustered && self.cluster_heatmap) as u32,
+            };
+
+            self.fs_uniform_buffer
+                .from_data(uniform_data)
+                .map_err(Error::Buffer)?
+        };
+
+        // A buffer can't be empty. Without lights `light_count` keeps the shaders off the
+        // placeholder.
+        let shader_lights: Vec<fs::ty::Light> = match lights.is_empty() {
+            true => vec![Zeroable::zeroed()],
+            false => lights.iter().map(shader_light).collect(),
+        };
+        let light_buffer = self
+            .light_buffer
+            .from_iter(shader_lights)
+            .map_err(Error::Buffer)?;
+        let (irradiance, prefiltered) = match environment {
+            Some(environment) => (&environment.irradiance, &environment.prefiltered),
+            None => (&self.black_cubemap, &self.black_cubemap),
+        };
+
+        // Every pass uses the same shaders, so their layouts are compatible.
+        let layout = pipelines[0].layout().set_layouts().get(0).unwrap();
+        let set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            layout.clone(),
+            [
+                WriteDescriptorSet::buffer(0, vs_uniform_buffer_subbuffer),
+                WriteDescriptorSet::buffer(1, fs_uniform_buffer_subbuffer),
+                WriteDescriptorSet::image_view_sampler(
+                    2,
+                    self.shadow_map.clone(),
+                    self.shadow_sampler.clone(),
+                ),
+                WriteDescriptorSet::buffer(3, light_buffer.clone()),
+                WriteDescriptorSet::buffer(4, self.cluster_light_counts.clone()),
+                WriteDescriptorSet::buffer(5, self.cluster_light_indices.clone()),
+                WriteDescriptorSet::image_view_sampler(
+                    6,
+                    irradiance.clone(),
+                    self.environment_sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view_sampler(
+                    7,
+                    prefiltered.clone(),
+                    self.environment_sampler.clone(),
+                ),
+                // Only read with an environment, which comes with the table.
+                WriteDescriptorSet::image_view_sampler(
+                    8,
+                    self.brdf_lut
+                        .as_ref()
+                        .unwrap_or(&self.white_texture)
+                        .clone(),
+                    self.environment_sampler.clone(),
+                ),
+            ],
+        )
+        .map_err(Error::DescriptorSet)?;
+
+        // Every mesh binds its own textures, in a set of its own so that the rest stays bound. In
+        // binding order: base colour, normal map, metallic-roughness and specular.
+        let texture_layout = pipelines[0].layout().set_layouts().get(1).unwrap();
+        let texture_set = |textures: [&Arc<ImageView<ImmutableImage>>; 4]| {
+            PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                texture_layout.clone(),
+                textures.into_iter().enumerate().map(|(binding, texture)| {
+                    WriteDescriptorSet::image_view_sampler(
+                        binding as u32,
+                        texture.clone(),
+                        self.texture_sampler.clone(),
+                    )
+                }),
+            )
+            .map_err(Error::DescriptorSet)
+        };
+        let texture_sets = self
+            .meshes
+            .iter()
+            .map(|mesh| {
+                texture_set([
+                    &mesh.texture,
+                    &mesh.normal_map,
+                    &mesh.metallic_roughness,
+                    &mesh.specular_map,
+                ])
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let white_texture_set = texture_set([
+            &self.white_texture,
+            &self.flat_normal_map,
+            &self.white_texture,
+            &self.white_texture,
+        ])?;
+
+        let instance_buffers = self
+            .meshes
+            .iter()
+            .map(|mesh| {
+                self.instance_buffer
+                    .from_iter(
+                        mesh.instances
+                            .iter()
+                            .map(|instance| monke_instance(instance, mesh, time)),
+                    )
+                    .map_err(Error::Buffer)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let visible = |mesh: &SceneMesh| !mesh.ground || level >= Levels::SIX;
+        // Only the lit levels have lights to show.
+        let gizmos: Vec<MonkeInstance> = match level >= Levels::FIVE {
+            true => lights.iter().filter_map(gizmo_instance).collect(),
+            false => vec![],
+        };
+        let gizmo_buffer = match gizmos.is_empty() {
+            true => None,
+            false => Some(
+                self.instance_buffer
+                    .from_iter(gizmos)
+                    .map_err(Error::Buffer)?,
+            ),
+        };
+
+        if clustered {
+            let uniform_data = cluster_cs::ty::Data {
+                view: view.to_cols_array_2d(),
+                inverse_projection: Mat4::from_cols_array_2d(&proj).inverse().to_cols_array_2d(),
+                light_count: lights.len() as u32,
+                near: NEAR,
+                far: FAR,
+            };
+            let cluster_set = PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                self.cluster_pipeline.layout().set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::buffer(0, light_buffer),
+                    WriteDescriptorSet::buffer(1, self.cluster_light_counts.clone()),
+                    WriteDescriptorSet::buffer(2, self.cluster_light_indices.clone()),
+                    WriteDescriptorSet::buffer(
+                        3,
+                        self.cluster_uniform_buffer
+                            .from_data(uniform_data)
+                            .map_err(Error::Buffer)?,
+                    ),
+                ],
+            )
+            .map_err(Error::DescriptorSet)?;
+            // One workgroup is one slice of the grid.
+            builder
+                .bind_pipeline_compute(self.cluster_pipeline.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    self.cluster_pipeline.layout().clone(),
+                    0,
+                    cluster_set,
+                )
+                .dispatch([1, 1, CLUSTER_GRID[2]])
+                .map_err(Error::Dispatch)?;
+        }
+
+        if shadows {
+            builder
+                .begin_render_pass(
+                    RenderPassBeginInfo {
+                        clear_values: vec![Some(1f32.into())],
+                        ..RenderPassBeginInfo::framebuffer(self.shadow_framebuffer.clone())
+                    },
+                    SubpassContents::Inline,
+                )
+                .map_err(Error::RenderPassCommand)?
+                .bind_pipeline_graphics(self.shadow_pipeline.clone())
+                .push_constants(
+                    self.shadow_pipeline.layout().clone(),
+                    0,
+                    shadow_vs::ty::PushConstants {
+                        light_space: self.light_space.to_cols_array_2d(),
+                    },
+                );
+            for (mesh, instance_buffer) in self.meshes.iter().zip(&instance_buffers) {
+                if !mesh.ground {
+                    draw_instances(
+                        builder,
+                        &mesh.vertex_buffer,
+                        &mesh.index_buffer,
+                        instance_buffer,
+                    )?;
+                }
+            }
+            builder
+                .end_render_pass()
+                .map_err(Error::RenderPassCommand)?;
+        }
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: clear_values(self.multisampled(level), self.clear_colour),
+                    ..RenderPassBeginInfo::framebuffer(target.scene)
+                },
+                SubpassContents::Inline,
+            )
+            .map_err(Error::RenderPassCommand)?;
+        for pipeline in pipelines {
+            builder
+                .bind_pipeline_graphics(pipeline.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    0,
+                    set.clone(),
+                );
+            for ((mesh, instance_buffer), texture_set) in
+                self.meshes.iter().zip(&instance_buffers).zip(&texture_sets)
+            {
+                if visible(mesh) {
+                    builder.bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        pipeline.layout().clone(),
+                        1,
+                        texture_set.clone(),
+                    );
+                    draw_instances(
+                        builder,
+                        &mesh.vertex_buffer,
+                        &mesh.index_buffer,
+                        instance_buffer,
+                    )?;
+                }
+            }
+            if let Some(gizmo_buffer) = &gizmo_buffer {
+                builder.bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    1,
+                    white_texture_set.clone(),
+                );
+                draw_instances(
+                    builder,
+                    &self.gizmo_vertices,
+                    &self.gizmo_indices,
+                    gizmo_buffer,
+                )?;
+            }
+        }
+
+        // Last, so that only the pixels the scene left empty are filled in.
+        if let Some(environment) = environment {
+            let pipeline = match &self.sky_pipeline_1 {
+                Some(pipeline) if self.multisampled(level) => pipeline,
+                _ => &self.sky_pipeline_2,
+            };
+            // The sky is infinitely far away, only the rotation of the camera moves it.
+            let rotation = Mat4::from_mat3(Mat3::from_mat4(view));
+            let view_projection = Mat4::from_cols_array_2d(&proj) * rotation;
+            let sky_set = PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                pipeline.layout().set_layouts()[0].clone(),
+                [WriteDescriptorSet::image_view_sampler(
+                    0,
+                    environment.sky.clone(),
+                    self.environment_sampler.clone(),
+                )],
+            )
+            .map_err(Error::DescriptorSet)?;
+            builder
+                .bind_pipeline_graphics(pipeline.clone())
+                .set_viewport(0, [full_viewport([width, height])])
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    0,
+                    sky_set,
+                )
+                .push_constants(
+                    pipeline.layout().clone(),
+                    0,
+                    sky_vs::ty::PushConstants {
+                        inverse_view_projection: view_projection.inverse().to_cols_array_2d(),
+                    },
+                )
+                .draw(3, 1, 0, 0)
+                .map_err(Error::Draw)?;
+        }
+        builder
+            .end_render_pass()
+            .map_err(Error::RenderPassCommand)?;
+
+        let tonemapped = level >= Levels::TWELVE;
+        let auto_exposure = tonemapped && self.auto_exposure;
+        // `texelFetch` doesn't filter, any sampler does for the HDR target.
+        let hdr_sampler = &self.texture_sampler;
+        if auto_exposure {
+            let histogram_set = PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                self.histogram_pipeline.layout().set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::image_view_sampler(
+                        0,
+                        target.hdr.clone(),
+                        hdr_sampler.clone(),
+                    ),
+                    WriteDescriptorSet::buffer(1, self.luminance_histogram.clone()),
+                ],
+            )
+            .map_err(Error::DescriptorSet)?;
+            let exposure_set = PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                self.exposure_pipeline.layout().set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::buffer(0, self.luminance_histogram.clone()),
+                    WriteDescriptorSet::buffer(1, self.average_luminance.clone()),
+                ],
+            )
+            .map_err(Error::DescriptorSet)?;
+            builder
+                .bind_pipeline_compute(self.histogram_pipeline.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    self.histogram_pipeline.layout().clone(),
+                    0,
+                    histogram_set,
+                )
+                .dispatch([
+                    width.div_ceil(HISTOGRAM_WORKGROUP_SIZE),
+                    height.div_ceil(HISTOGRAM_WORKGROUP_SIZE),
+                    1,
+                ])
+                .map_err(Error::Dispatch)?
+                .bind_pipeline_compute(self.exposure_pipeline.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    self.exposure_pipeline.layout().clone(),
+                    0,
+                    exposure_set,
+                )
+                .push_constants(
+                    self.exposure_pipeline.layout().clone(),
+                    0,
+                    exposure_cs::ty::PushConstants {
+                        pixel_count: width * height,
+                        adaptation: 1.0 - (-self.frame_time * EXPOSURE_ADAPTATION_RATE).exp(),
+                    },
+                )
+                .dispatch([1, 1, 1])
+                .map_err(Error::Dispatch)?;
+        }
+
+        let output_set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            self.output_pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view_sampler(0, target.hdr, hdr_sampler.clone()),
+                WriteDescriptorSet::buffer(1, self.average_luminance.clone()),
+            ],
+        )
+        .map_err(Error::DescriptorSet)?;
+        // Before the tonemapping level the colour comes out as it was drawn.
+        let (exposure, tonemapper) = match tonemapped {
+            true => (self.exposure.exp2(), tonemapper_kind(self.tonemapper)),
+            false => (1.0, CLIP),
+        };
+        let push_constants = tonemap_fs::ty::PushConstants {
+            exposure,
+            tonemapper,
+            auto_exposure: auto_exposure as u32,
+            encode_srgb: self.encode_srgb as u32,
+            gamma_comparison: self.gamma_comparison as u32,
+        };
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(target.output)
+                },
+                SubpassContents::Inline,
+            )
+            .map_err(Error::RenderPassCommand)?
+            .bind_pipeline_graphics(self.output_pipeline.clone())
+            .set_viewport(0, [full_viewport([width, height])])
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.output_pipeline.layout().clone(),
+                0,
+                output_set,
+            )
+            .push_constants(self.output_pipeline.layout().clone(), 0, push_constants)
+            .draw(3, 1, 0, 0)
+            .map_err(Error::Draw)?
+            .end_render_pass()
+            .map_err(Error::RenderPassCommand)?;
+        Ok(())
+    }
+}
+
+/// Draws the mesh in `vertex_buffer` and `index_buffer` once for every instance in
+/// `instance_buffer`, with whatever pipeline is bound.
+fn draw_instances(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    vertex_buffer: &Arc<CpuAccessibleBuffer<[Vertex]>>,
+    index_buffer: &Arc<CpuAccessibleBuffer<[u32]>>,
+    instance_buffer: &Arc<CpuBufferPoolChunk<MonkeInstance>>,
+) -> Result<(), Error> {
+    builder
+        .bind_vertex_buffers(0, (vertex_buffer.clone(), instance_buffer.clone()))
+        .bind_index_buffer(index_buffer.clone())
+        .draw_indexed(
+            index_buffer.len() as u32,
+            instance_buffer.len() as u32,
+            0,
+            0,
+            0,
+        )
+        .map_err(Error::Draw)?;
+    Ok(())
+}
+
+/// The sample counts a transient attachment in `format`, like the multisampled ones, can have.
+fn attachment_sample_counts(device: &Device, format: Format) -> Result<SampleCounts, Error> {
+    let depth = format.aspects().depth;
+    let properties = device
+        .physical_device()
+        .image_format_properties(ImageFormatInfo {
+            format: Some(format),
+            usage: ImageUsage {
+                transient_attachment: true,
+                color_attachment: !depth,
+                depth_stencil_attachment: depth,
+                ..ImageUsage::empty()
+            },
+            ..Default::default()
+        })
+        .map_err(Error::FormatProperties)?;
+    // None if the format can't be used like that at all.
+    Ok(properties.map_or_else(SampleCounts::empty, |properties| properties.sample_counts))
+}
+
+/// Records the uploads with `record` and waits until they are done.
+fn run_uploads<T>(
+    command_buffer_allocator: &StandardCommandBufferAllocator,
+    queue: &Arc<Queue>,
+    record: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let mut builder = AutoCommandBufferBuilder::primary(
+        command_buffer_allocator,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .map_err(Error::CommandBuffer)?;
+    let uploaded = record(&mut builder)?;
+    builder
+        .build()
+        .map_err(Error::BuildCommandBuffer)?
+        .execute(queue.clone())
+        .map_err(Error::Execute)?
+        .then_signal_fence_and_flush()
+        .map_err(Error::Flush)?
+        .wait(None)
+        .map_err(Error::Flush)?;
+    Ok(uploaded)
+}
+
+/// Uploads `textures` in their formats with a full chain of mipmaps, which the GPU blits down from
+/// the top level, and waits until they are there.
+fn upload_textures(
+    memory_allocator: &StandardMemoryAllocator,
+    command_buffer_allocator: &StandardCommandBufferAllocator,
+    queue: &Arc<Queue>,
+    textures: &[(&Texture, Format)],
+) -> Result<Vec<Arc<ImageView<ImmutableImage>>>, Error> {
+    if textures.is_empty() {
+        return Ok(vec![]);
+    }
+    run_uploads(command_buffer_allocator, queue, |builder| {
+        textures
+            .iter()
+            .map(|&(texture, format)| {
+                let image = ImmutableImage::from_iter(
+                    memory_allocator,
+                    texture.pixels.iter().copied(),
+                    ImageDimensions::Dim2d {
+                        width: texture.width,
+                        height: texture.height,
+                        array_layers: 1,
+                    },
+                    MipmapsCount::Log2,
+                    format,
+                    builder,
+                )
+                .map_err(Error::Upload)?;
+                ImageView::new_default(image).map_err(Error::ImageView)
+            })
+            .collect()
+    })
+}
+
+/// Records the upload of `cubemap` with the mip levels it comes with, which can't be blitted since
+/// they are blurred rather than shrunk. Run it with `run_uploads`.
+fn upload_cubemap(
+    memory_allocator: &StandardMemoryAllocator,
+    queue: &Arc<Queue>,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    cubemap: &Cubemap,
+) -> Result<Arc<ImageView<ImmutableImage>>, Error> {
+    let texels: Vec<[u16; 4]> = cubemap
+        .levels
+        .iter()
+        .flatten()
+        .map(|texel| texel.extend(1.0).to_array().map(half_float))
+        .collect();
+    let buffer = CpuAccessibleBuffer::from_iter(
+        memory_allocator,
+        BufferUsage {
+            transfer_src: true,
+            ..BufferUsage::empty()
+        },
+        false,
+        texels,
+    )
+    .map_err(Error::Buffer)?;
+    let (image, initialization) = ImmutableImage::uninitialized(
+        memory_allocator,
+        ImageDimensions::Dim2d {
+            width: cubemap.size,
+            height: cubemap.size,
+            array_layers: 6,
+        },
+        ENVIRONMENT_FORMAT,
+        MipmapsCount::Specific(cubemap.levels.len() as u32),
+        ImageUsage {
+            transfer_dst: true,
+            sampled: true,
+            ..ImageUsage::empty()
+        },
+        ImageCreateFlags {
+            cube_compatible: true,
+            ..ImageCreateFlags::empty()
+        },
+        ImageLayout::ShaderReadOnlyOptimal,
+        [queue.queue_family_index()],
+    )
+    .map_err(Error::Upload)?;
+    // Every level holds all six faces, one after the other.
+    let texel_size = ENVIRONMENT_FORMAT.block_size().unwrap();
+    let mut offset = 0;
+    let regions = cubemap
+        .levels
+        .iter()
+        .enumerate()
+        .map(|(level, texels)| {
+            let size = (cubemap.size >> level).max(1);
+            let region = BufferImageCopy {
+                buffer_offset: offset,
+                image_subresource: ImageSubresourceLayers {
+                    mip_level: level as u32,
+                    ..ImageSubresourceLayers::from_parameters(ENVIRONMENT_FORMAT, 6)
+                },
+                image_extent: [size, size, 1],
+                ..Default::default()
+            };
+            offset += texels.len() as u64 * texel_size;
+            region
+        })
+        .collect();
+    builder
+        .copy_buffer_to_image(CopyBufferToImageInfo {
+            regions,
+            ..CopyBufferToImageInfo::buffer_image(buffer, initialization)
+        })
+        .map_err(Error::Copy)?;
+    ImageView::new(
+        image.clone(),
+        ImageViewCreateInfo {
+            view_type: ImageViewType::Cube,
+            ..ImageViewCreateInfo::from_image(&image)
+        },
+    )
+    .map_err(Error::ImageView)
+}
+
+fn upload_brdf_lut(
+    memory_allocator: &StandardMemoryAllocator,
+    command_buffer_allocator: &StandardCommandBufferAllocator,
+    queue: &Arc<Queue>,
+) -> Result<Arc<ImageView<ImmutableImage>>, Error> {
+    run_uploads(command_buffer_allocator, queue, |builder| {
+        let image = ImmutableImage::from_iter(
+            memory_allocator,
+            environment::brdf_lut()
+                .iter()
+                .map(|texel| texel.map(half_float)),
+            ImageDimensions::Dim2d {
+                width: environment::BRDF_LUT_SIZE,
+                height: environment::BRDF_LUT_SIZE,
+                array_layers: 1,
+            },
+            MipmapsCount::One,
+            BRDF_LUT_FORMAT,
+            builder,
+        )
+        .map_err(Error::Upload)?;
+        ImageView::new_default(image).map_err(Error::ImageView)
+    })
+}
+
+/// `value` as a half float, clamped to the largest one so that a bright sun doesn't turn infinite.
+fn half_float(value: f32) -> u16 {
+    f16::from_f32(value.min(f16::MAX.to_f32())).to_bits()
+}
+
+/// An octahedron around the origin.
+fn gizmo_mesh() -> (Vec<Vertex>, Vec<u32>) {
+    let corners = [Vec3::X, Vec3::Y, -Vec3::X, -Vec3::Y].map(|corner| corner * GIZMO_SIZE);
+    let mut vertices = vec![];
+    for tip in [Vec3::Z, -Vec3::Z].map(|tip| tip * GIZMO_SIZE) {
+        for i in 0..corners.len() {
+            let (a, b) = (corners[i], corners[(i + 1) % corners.len()]);
+            // Both tips wound counter-clockwise seen from outside.
+            let [a, b] = if tip.z > 0.0 { [a, b] } else { [b, a] };
+            let normal = (a - tip).cross(b - tip).normalize();
+            vertices.extend([tip, a, b].map(|position| Vertex {
+                position: position.into(),
+                normal: normal.into(),
+                uv: [0.0; 2],
+                tangent: [0.0; 4],
+            }));
+        }
+    }
+    let indices = (0..vertices.len() as u32).collect();
+    (vertices, indices)
+}
+
+/// A gizmo at a point or spot light, glowing in its colour. Directional lights get none.
+fn gizmo_instance(light: &Light) -> Option<MonkeInstance> {
+    let model = Mat4::from_translation(light.position()?);
+    Some(MonkeInstance {
+        model: model.to_cols_array_2d(),
+        normal_matrix: Mat3::IDENTITY.to_cols_array_2d(),
+        colour: [0.0; 3],
+        specular: [0.0; 3],
+        surface: [1.0, 0.0, 1.0],
+        emissive: light.colour().into(),
+    })
+}
+
+/// Only clips, for the levels before tonemapping. The others are the ones of `tonemapper_kind`.
+const CLIP: u32 = 0;
+
+/// What `tonemap.frag` calls `tonemapper`.
+fn tonemapper_kind(tonemapper: Tonemapper) -> u32 {
+    // The kinds in `tonemap.frag`.
+    const ACES: u32 = 1;
+    const REINHARD: u32 = 2;
+    const FILMIC: u32 = 3;
+
+    match tonemapper {
+        Tonemapper::Aces => ACES,
+        Tonemapper::Reinhard => REINHARD,
+        Tonemapper::Filmic => FILMIC,
+    }
+}
+
+/// The layout `shader.frag` reads the lights in.
+fn shader_light(light: &Light) -> fs::ty::Light {
+    // The kinds in `shader.frag`.
+    const DIRECTIONAL: u32 = 0;
+    const POINT: u32 = 1;
+    const SPOT: u32 = 2;
+
+    let radiance = |colour: [f32; 3], intensity: f32| (Vec3::from(colour) * intensity).extend(1.0);
+    let mut shader_light = fs::ty::Light::zeroed();
+    match *light {
+        Light::Directional {
+            direction,
+            colour,
+            intensity,
+        } => {
+            shader_light.kind = DIRECTIONAL;
+            shader_light.direction = Vec3::from(direction).extend(0.0).into();
+            shader_light.colour = radiance(colour, intensity).into();
+        }
+        Light::Point {
+            position,
+            colour,
+            intensity,
+            range,
+        } => {
+            shader_light.kind = POINT;
+            shader_light.position = Vec3::from(position).extend(1.0).into();
+            shader_light.colour = radiance(colour, intensity).into();
+            shader_light.range = range;
+        }
+        Light::Spot {
+            position,
+            direction,
+            colour,
+            intensity,
+            range,
+            inner_angle,
+            outer_angle,
+        } => {
+            shader_light.kind = SPOT;
+            shader_light.position = Vec3::from(position).extend(1.0).into();
+            shader_light.direction = Vec3::from(direction).extend(0.0).into();
+            shader_light.colour = radiance(colour, intensity).into();
+            shader_light.range = range;
+            shader_light.cos_inner = deg_2_rad(inner_angle).cos();
+            shader_light.cos_outer = deg_2_rad(outer_angle).cos();
+        }
+    }
+    shader_light
+}
+
+/// Depth only, with the depth pushed away from the light a little so that surfaces don't shadow
+/// themselves.
+fn shadow_pipeline(
+    device: Arc<Device>,
+    vs: &ShaderModule,
+    render_pass: Arc<RenderPass>,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    GraphicsPipeline::start()
+        .vertex_input_state(
+            BuffersDefinition::new()
+                .vertex::<Vertex>()
+                .instance::<MonkeInstance>(),
+        )
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([
+            Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [SHADOW_MAP_SIZE as f32; 2],
+                depth_range: 0.0..1.0,
+            },
+        ]))
+        .rasterization_state(RasterizationState {
+            depth_bias: Some(DepthBiasState {
+                enable_dynamic: false,
+                bias: StateMode::Fixed(DepthBias {
+                    constant_factor: 1.25,
+                    clamp: 0.0,
+                    slope_factor: 1.75,
+                }),
+            }),
+            ..RasterizationState::new()
+        })
+        .depth_stencil_state(DepthStencilState::simple_depth_test())
+        .render_pass(Subpass::from(render_pass, 0).unwrap())
+        .build(device)
+}
+
+/// Fills whatever the scene left at the far plane with the sky. Nothing is written to the depth
+/// buffer, and there are no vertices: `sky.vert` makes a triangle that covers the whole screen.
+fn sky_pipeline(
+    vs: &ShaderModule,
+    fs: &ShaderModule,
+    render_pass: Arc<RenderPass>,
+    samples: SampleCount,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    let device = render_pass.device().clone();
+    GraphicsPipeline::start()
+        .vertex_input_state(BuffersDefinition::new())
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .rasterization_state(RasterizationState::new())
+        .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .depth_stencil_state(DepthStencilState {
+            depth: Some(DepthState {
+                enable_dynamic: false,
+                write_enable: StateMode::Fixed(false),
+                compare_op: StateMode::Fixed(CompareOp::LessOrEqual),
+            }),
+            ..DepthStencilState::disabled()
+        })
+        .multisample_state(MultisampleState {
+            rasterization_samples: samples,
+            ..Default::default()
+        })
+        .render_pass(Subpass::from(render_pass, 0).unwrap())
+        .build(device)
+}
+
+/// Draws the fullscreen triangle of `fullscreen.vert` with `fs`, without depth.
+fn output_pipeline(
+    vs: &ShaderModule,
+    fs: &ShaderModule,
+    render_pass: Arc<RenderPass>,
+) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+    let device = render_pass.device().clone();
+    GraphicsPipeline::start()
+        .vertex_input_state(BuffersDefinition::new())
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .rasterization_state(RasterizationState::new())
+        .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .render_pass(Subpass::from(render_pass, 0).unwrap())
+        .build(device)
+}
+
+/// Covers an image of `dimensions`, for the pipelines with a dynamic viewport.
+fn full_viewport(dimensions: [u32; 2]) -> Viewport {
+    Viewport {
+        origin: [0.0, 0.0],
+        dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+        depth_range: 0.0..1.0,
+    }
+}
+
+/// Orthographic projection along `direction`, towards the light, that takes in a sphere around
+/// `bounds`. The sphere is a bit larger than the bounds, which leaves room for the animations.
+fn light_space(direction: Vec3, bounds: Aabb) -> Mat4 {
+    let direction = direction.normalize_or_zero();
+    let center = bounds.center();
+    let radius = bounds.radius().max(1.0) * 1.5;
+    // Any up vector works as long as it isn't parallel to the light.
+    let up = if direction.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let view = Mat4::look_at_rh(center + direction * radius * 2.0, center, up);
+    let projection = Mat4::orthographic_rh(-radius, radius, -radius, radius, radius, radius * 3.0);
+    projection * view
+}
+
+fn clear_values(multisampled: bool, clear_colour: [f32; 4]) -> Vec<Option<ClearValue>> {
+    if multisampled {
+        vec![
+            Some(clear_colour.into()),
+            Some(clear_colour.into()),
+            Some(1f32.into()),
+        ]
+    } else {
+        vec![Some(clear_colour.into()), Some(1f32.into())]
+    }
+}
+
+/// Draws into attachments with `samples` samples per pixel and resolves them into the HDR target.
+/// `None` for a single sample, which has nothing to resolve.
+fn multisampled_render_pass(
+    device: Arc<Device>,
+    samples: SampleCount,
+) -> Result<Option<Arc<RenderPass>>, Error> {
+    if samples == SampleCount::Sample1 {
+        return Ok(None);
+    }
+    vulkano::single_pass_renderpass!(device,
+        attachments: {
+            intermediary: {
+                load: Clear,
+                store: DontCare,
+                format: HDR_FORMAT,
+                samples: samples as u32,
+            },
+            color: {
+                load: Clear,
+                store: Store,
+                format: HDR_FORMAT,
+                samples: 1,
+            },
+            depth: {
+                load: Clear,
+                store: DontCare,
+                format: Format::D16_UNORM,
+                samples: samples as u32,
+            }
+        },
+        pass: {
+            color: [intermediary],
+            depth_stencil: {depth},
+            resolve: [color]
+        }
+    )
+    .map(Some)
+    .map_err(Error::RenderPass)
+}
+
+/// Picks the device to render with and the queue family to use on it. The queue family has to
+/// support graphics and, when there is a `surface`, presenting to it. Without a suitable device
+/// the error names every device that was found and what it lacks.
+pub fn pick_device(
+    instance: &Arc<Instance>,
+    extensions: &DeviceExtensions,
+    features: &Features,
+    surface: Option<&Surface>,
+) -> Result<(Arc<PhysicalDevice>, u32), Error> {
+    let mut rejected = vec![];
+    let mut suitable = vec![];
+    for device in instance
+        .enumerate_physical_devices()
+        .map_err(Error::Devices)?
+    {
+        let missing_extensions = extensions.difference(device.supported_extensions());
+        let missing_features = features.difference(device.supported_features());
+        let queue_family_index = device
+            .queue_family_properties()
+            .iter()
+            .enumerate()
+            .position(|(i, q)| {
+                q.queue_flags.graphics
+                    && surface.is_none_or(|surface| {
+                        device.surface_support(i as u32, surface).unwrap_or(false)
+                    })
+            });
+        let problem = if missing_extensions != DeviceExtensions::empty() {
+            format!("missing extensions {:?}", missing_extensions)
+        } else if missing_features != Features::empty() {
+            format!("missing features {:?}", missing_features)
+        } else if let Some(index) = queue_family_index {
+            suitable.push((device, index as u32));
+            continue;
+        } else if surface.is_some() {
+            "no graphics queue can present to the window".to_string()
+        } else {
+            "no graphics queue".to_string()
+        };
+        rejected.push((device.properties().device_name.clone(), problem));
+    }
+
+    suitable
+        .into_iter()
+        .min_by_key(|(device, _)| device_type_rank(device.properties().device_type))
+        .ok_or(Error::NoDevice(
+            match surface {
+                Some(_) => "supports presentation to this surface",
+                None => "can render offscreen",
+            },
+            rejected,
+        ))
+}
+
+/// The format and colour space to present in. An sRGB format encodes the linear colour of the
+/// shaders on its own. Without one, a UNORM format in the sRGB colour space is the fallback and the
+/// output pass encodes the colour itself. Drivers list their formats in no particular order, so the
+/// first one is only taken when neither is there.
+pub fn pick_surface_format(formats: &[(Format, ColorSpace)]) -> (Format, ColorSpace) {
+    let in_srgb_space = |numeric_type| {
+        formats.iter().copied().find(|&(format, colour_space)| {
+            colour_space == ColorSpace::SrgbNonLinear && format.type_color() == Some(numeric_type)
+        })
+    };
+    in_srgb_space(NumericType::SRGB)
+        .or_else(|| in_srgb_space(NumericType::UNORM))
+        .unwrap_or(formats[0])
+}
+
+/// Lower is better. Used to pick a physical device when there are several.
+fn device_type_rank(device_type: PhysicalDeviceType) -> u32 {
+    match device_type {
+        PhysicalDeviceType::DiscreteGpu => 0,
+        PhysicalDeviceType::IntegratedGpu => 1,
+        PhysicalDeviceType::VirtualGpu => 2,
+        PhysicalDeviceType::Cpu => 3,
+        PhysicalDeviceType::Other => 4,
+        _ => 5,
+    }
+}
+
+fn monke_instance(instance: &scene::Instance, mesh: &SceneMesh, time: f32) -> MonkeInstance {
+    let model = instance.location_at(time).model_matrix() * mesh.transform;
+    MonkeInstance {
+        model: model.to_cols_array_2d(),
+        normal_matrix: normal_matrix(model).to_cols_array_2d(),
+        colour: (instance.colour_at(time) * Vec4::from(mesh.material.base_colour).truncate())
+            .into(),
+        specular: instance.specular.unwrap_or(mesh.material.specular),
+        surface: [
+            instance.shininess.unwrap_or(mesh.material.shininess),
+            instance.metallic.unwrap_or(mesh.material.metallic),
+            instance.roughness.unwrap_or(mesh.material.roughness),
+        ],
+        emissive: mesh.material.emissive,
+    }
+}
+
+/// The inverse transpose of the rotation and scale of `model`, which keeps normals perpendicular
+/// to the surface however it is stretched.
+fn normal_matrix(model: Mat4) -> Mat3 {
+    let linear = Mat3::from_mat4(model);
+    // Compared with the volume of the box spanned by the axes, so that small instances still
+    // count. A scale of zero flattens the instance so that there is nothing to invert, and
+    // nothing to see either, so any normals will do as long as they aren't NaN.
+    let volume = linear.x_axis.length() * linear.y_axis.length() * linear.z_axis.length();
+    match linear.determinant().abs() > volume * 1e-6 {
+        true => linear.inverse().transpose(),
+        false => Mat3::IDENTITY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_matrix_test() {
+        let rotation = Mat4::from_rotation_y(deg_2_rad(90.0));
+        let normal = Vec3::new(1.0, 0.0, 0.0);
+        let turned = Vec3::new(0.0, 0.0, -1.0);
+
+        for scale in [1.0, 0.001, 1e-5] {
+            let model = Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0))
+                * rotation
+                * Mat4::from_scale(Vec3::splat(scale));
+            let transformed = (normal_matrix(model) * normal).normalize();
+            assert!(
+                transformed.abs_diff_eq(turned, 1e-5),
+                "{}: {}",
+                scale,
+                transformed
+            );
+        }
+
+        // Squashed along y, the normal of a slope leans towards y.
+        let model = Mat4::from_scale(Vec3::new(1.0, 0.5, 1.0));
+        let slope = (normal_matrix(model) * Vec3::new(1.0, 1.0, 0.0)).normalize();
+        assert!(slope.abs_diff_eq(Vec3::new(1.0, 2.0, 0.0).normalize(), 1e-6));
+    }
+
+    #[test]
+    fn flat_normal_matrix_test() {
+        for scale in [Vec3::ZERO, Vec3::new(1.0, 0.0, 1.0)] {
+            let model = Mat4::from_rotation_y(1.0) * Mat4::from_scale(scale);
+            assert_eq!(normal_matrix(model), Mat3::IDENTITY);
+        }
+    }
+}
@@ -0,0 +1,905 @@
+//! Scene description files.
+//!
+//! A scene is a RON file listing the meshes to load, the instances to draw with them, where the
+//! camera starts, the clear colour and the lights. See `scenes/default.ron` for an example. Mesh
+//! paths are relative to the scene file and can point at OBJ, glTF or GLB files, texture paths at
+//! PNG or JPEG files and the environment at a Radiance HDR file. All of them are found through
+//! `assets`. Instances can be animated, see `animation`. Colours are linear, they are only encoded
+//! for the screen once the frame is done.
+
+use crate::animation::Animation;
+use crate::assets::{Asset, AssetError, Assets};
+use crate::mesh::MeshError;
+
+use math::{deg_2_rad, rad_2_deg, translation_from_matrix, Location, Mat4, Vec3};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Rows and columns of `Scene::material_grid`.
+const GRID_SIZE: usize = 7;
+/// Between the centres of neighbouring instances in the grid, enough for an unscaled monke.
+const GRID_SPACING: f32 = 3.0;
+
+#[derive(Debug, Deserialize)]
+pub struct Scene {
+    #[serde(default)]
+    pub camera: CameraStart,
+    #[serde(default = "default_clear_colour")]
+    pub clear_colour: [f32; 4],
+    #[serde(default)]
+    pub lights: Vec<Light>,
+    #[serde(default)]
+    pub swarm: Option<Swarm>,
+    #[serde(default)]
+    pub ground: Option<Ground>,
+    #[serde(default)]
+    pub environment: Option<Environment>,
+    /// Mesh name to path, as written in the file.
+    #[serde(rename = "meshes")]
+    mesh_paths: HashMap<String, PathBuf>,
+    /// Mesh name to where `load` found it.
+    #[serde(skip)]
+    pub meshes: HashMap<String, Asset>,
+    /// Texture name to path, as written in the file.
+    #[serde(default, rename = "textures")]
+    texture_paths: HashMap<String, PathBuf>,
+    /// Texture name to where `load` found it.
+    #[serde(skip)]
+    pub textures: HashMap<String, Asset>,
+    /// Instances of the same mesh are drawn in the order they are listed, which matters for the
+    /// levels without depth testing.
+    pub instances: Vec<Instance>,
+    /// Where `load` found the scene file itself.
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CameraStart {
+    pub eye: [f32; 3],
+    /// Degrees, positive turns right.
+    #[serde(default)]
+    pub yaw: f32,
+    /// Degrees, positive looks down.
+    #[serde(default)]
+    pub pitch: f32,
+}
+
+impl CameraStart {
+    /// A camera at the origin of `transform`, looking down its -z axis like glTF cameras do. Any
+    /// roll is lost.
+    pub fn from_transform(transform: Mat4) -> Self {
+        let forward = transform.transform_vector3(-Vec3::Z).normalize_or_zero();
+        Self {
+            eye: translation_from_matrix(transform).into(),
+            yaw: rad_2_deg(forward.x.atan2(-forward.z)),
+            pitch: rad_2_deg((-forward.y).clamp(-1.0, 1.0).asin()),
+        }
+    }
+}
+
+impl Default for CameraStart {
+    fn default() -> Self {
+        Self {
+            eye: [0.0, 0.0, 2.0],
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+/// Positions and directions are in world space. Point and spot lights fade with the square of the
+/// distance, so they need a higher `intensity` than a directional light, and reach nothing past
+/// their `range`.
+#[derive(Clone, Debug, Deserialize)]
+pub enum Light {
+    Directional {
+        /// Points towards the light.
+        direction: [f32; 3],
+        #[serde(default = "white")]
+        colour: [f32; 3],
+        #[serde(default = "one")]
+        intensity: f32,
+    },
+    Point {
+        position: [f32; 3],
+        #[serde(default = "white")]
+        colour: [f32; 3],
+        #[serde(default = "one")]
+        intensity: f32,
+        #[serde(default = "default_range")]
+        range: f32,
+    },
+    Spot {
+        position: [f32; 3],
+        /// The way the light shines.
+        direction: [f32; 3],
+        #[serde(default = "white")]
+        colour: [f32; 3],
+        #[serde(default = "one")]
+        intensity: f32,
+        #[serde(default = "default_range")]
+        range: f32,
+        /// Degrees from the middle of the cone to where the light starts fading out.
+        #[serde(default = "default_inner_angle")]
+        inner_angle: f32,
+        /// Degrees from the middle of the cone to where the light is gone.
+        #[serde(default = "default_outer_angle")]
+        outer_angle: f32,
+    },
+}
+
+impl Light {
+    /// Directional lights have none, they are infinitely far away.
+    pub fn position(&self) -> Option<Vec3> {
+        match *self {
+            Light::Directional { .. } => None,
+            Light::Point { position, .. } | Light::Spot { position, .. } => Some(position.into()),
+        }
+    }
+
+    pub fn colour(&self) -> Vec3 {
+        match *self {
+            Light::Directional { colour, .. }
+            | Light::Point { colour, .. }
+            | Light::Spot { colour, .. } => colour.into(),
+        }
+    }
+
+    /// Moves a point or spot light by `offset`. A directional light has nowhere to move, its
+    /// direction is tilted towards `offset` instead.
+    pub fn move_by(&mut self, offset: Vec3) {
+        match self {
+            Light::Directional { direction, .. } => {
+                *direction = (Vec3::from(*direction) + offset).into();
+            }
+            Light::Point { position, .. } | Light::Spot { position, .. } => {
+                *position = (Vec3::from(*position) + offset).into();
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        match *self {
+            Light::Directional { direction, .. } if Vec3::from(direction) == Vec3::ZERO => {
+                Err("the direction is zero")
+            }
+            Light::Point { range, .. } | Light::Spot { range, .. } if range <= 0.0 => {
+                Err("the range has to be positive")
+            }
+            Light::Spot { direction, .. } if Vec3::from(direction) == Vec3::ZERO => {
+                Err("the direction is zero")
+            }
+            Light::Spot {
+                inner_angle,
+                outer_angle,
+                ..
+            } if inner_angle < 0.0 || inner_angle > outer_angle || outer_angle >= 90.0 => {
+                Err("the angles need 0 <= inner_angle <= outer_angle < 90")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Lots of small point lights in every colour, each circling around a random spot in a box. Made
+/// to show off the clustered lighting level.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Swarm {
+    pub count: u32,
+    /// Corners of the box the lights circle around in.
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    #[serde(default = "one")]
+    pub intensity: f32,
+    #[serde(default = "default_swarm_range")]
+    pub range: f32,
+    /// Radius of the circles.
+    #[serde(default = "one")]
+    pub wander: f32,
+}
+
+impl Swarm {
+    /// Where the lights are `time` seconds in. The same every run.
+    pub fn lights_at(&self, time: f32) -> impl Iterator<Item = Light> + '_ {
+        (0..self.count).map(move |index| {
+            let random = |salt| random(index, salt);
+            let (min, max) = (Vec3::from(self.min), Vec3::from(self.max));
+            let start = min + (max - min) * Vec3::new(random(0), random(1), random(2));
+            let angle = random(3) * TAU + time * (0.5 + random(4));
+            let offset = Vec3::new(angle.cos(), (angle * 2.0).sin() * 0.25, angle.sin());
+            Light::Point {
+                position: (start + offset * self.wander).into(),
+                colour: hue(random(5)),
+                intensity: self.intensity,
+                range: self.range,
+            }
+        })
+    }
+}
+
+/// A flat square under the scene that catches the shadows. Only drawn from the shadow level on.
+#[derive(Debug, Deserialize)]
+pub struct Ground {
+    /// Just under the lowest instance by default.
+    #[serde(default)]
+    pub height: Option<f32>,
+    #[serde(default = "grey")]
+    pub colour: [f32; 3],
+}
+
+/// An equirectangular image of the surroundings. Drawn behind the scene and lighting it from every
+/// direction on the image based lighting level.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Environment {
+    /// Relative to the scene file, like the meshes.
+    path: PathBuf,
+    /// Scales the light of the whole image.
+    #[serde(default = "one")]
+    pub intensity: f32,
+    /// Where `Scene::load` found the image.
+    #[serde(skip)]
+    pub image: Option<Asset>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Instance {
+    /// Key into `Scene::meshes`.
+    pub mesh: String,
+    #[serde(default)]
+    pub position: [f32; 3],
+    /// Euler angles in degrees, applied in XYZ order.
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    #[serde(default)]
+    pub scale: Scale,
+    #[serde(default = "white")]
+    pub colour: [f32; 3],
+    /// Colour of the highlights, the material's by default.
+    #[serde(default)]
+    pub specular: Option<[f32; 3]>,
+    /// Blinn-Phong exponent, the higher the smaller and sharper the highlights. The material's by
+    /// default.
+    #[serde(default)]
+    pub shininess: Option<f32>,
+    /// 0 for a dielectric, 1 for a metal, the material's by default.
+    #[serde(default)]
+    pub metallic: Option<f32>,
+    /// From 0 for a mirror to 1 for chalk, the material's by default.
+    #[serde(default)]
+    pub roughness: Option<f32>,
+    /// Key into `Scene::textures`, replaces the base colour texture of the material.
+    #[serde(default)]
+    pub texture: Option<String>,
+    /// Key into `Scene::textures`, replaces the normal map of the material.
+    #[serde(default)]
+    pub normal_map: Option<String>,
+    /// Draws only the part of the mesh with this name, an OBJ object or group or a glTF mesh.
+    /// Otherwise every part is drawn.
+    #[serde(default)]
+    pub part: Option<String>,
+    #[serde(default)]
+    pub animation: Option<Animation>,
+}
+
+impl Instance {
+    /// Where the instance is `time` seconds after its animation started.
+    pub fn location_at(&self, time: f32) -> Location {
+        let mut location = Location::new(
+            self.rotation.map(deg_2_rad),
+            self.position,
+            self.scale.per_axis(),
+        );
+        if let Some(animation) = &self.animation {
+            let time = animation.local_time(time);
+            if let Some(track) = &animation.position {
+                location.position = track.sample(time);
+            }
+            if let Some(track) = &animation.rotation {
+                location.rotation = track.sample(time) * deg_2_rad(1.0);
+            }
+            if let Some(track) = &animation.scale {
+                location.scale = track.sample(time);
+            }
+        }
+        location
+    }
+
+    pub fn colour_at(&self, time: f32) -> Vec3 {
+        if let Some(animation) = &self.animation {
+            if let Some(track) = &animation.colour {
+                return track.sample(animation.local_time(time));
+            }
+        }
+        self.colour.into()
+    }
+}
+
+/// Either `scale: 2.0` or `scale: (1.0, 2.0, 1.0)`.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Scale {
+    Uniform(f32),
+    PerAxis([f32; 3]),
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Scale::Uniform(1.0)
+    }
+}
+
+impl Scale {
+    pub fn per_axis(self) -> [f32; 3] {
+        match self {
+            Scale::Uniform(scale) => [scale; 3],
+            Scale::PerAxis(scale) => scale,
+        }
+    }
+}
+
+/// The blue of sRGB (0.0, 0.2, 0.6).
+fn default_clear_colour() -> [f32; 4] {
+    [0.0, 0.0331, 0.3185, 1.0]
+}
+
+fn white() -> [f32; 3] {
+    [1.0; 3]
+}
+
+/// Half way between black and white on the screen.
+fn grey() -> [f32; 3] {
+    [0.214; 3]
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn default_range() -> f32 {
+    10.0
+}
+
+fn default_swarm_range() -> f32 {
+    2.0
+}
+
+/// Repeatable noise from 0 to 1 for the `salt`th random number of the `index`th light.
+fn random(index: u32, salt: u32) -> f32 {
+    let mut x = index.wrapping_mul(0x9E37_79B9) ^ salt.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+/// The fully saturated colour at `hue`, which goes from 0 to 1 once around the colour wheel.
+fn hue(hue: f32) -> [f32; 3] {
+    let h = hue * 6.0;
+    [
+        ((h - 3.0).abs() - 1.0).clamp(0.0, 1.0),
+        (2.0 - (h - 2.0).abs()).clamp(0.0, 1.0),
+        (2.0 - (h - 4.0).abs()).clamp(0.0, 1.0),
+    ]
+}
+
+fn default_inner_angle() -> f32 {
+    20.0
+}
+
+fn default_outer_angle() -> f32 {
+    30.0
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Asset(AssetError),
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    UnknownMesh(String),
+    Mesh(PathBuf, MeshError),
+    UnknownTexture(String),
+    Texture(PathBuf, image::ImageError),
+    Environment(PathBuf, image::ImageError),
+    /// Mesh name and part name.
+    UnknownPart(String, String),
+    /// Index of the instance and what is wrong with its animation.
+    Animation(usize, &'static str),
+    /// Index of the light and what is wrong with it.
+    Light(usize, &'static str),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Asset(e) => write!(f, "{}", e),
+            SceneError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            SceneError::Parse(path, e) => write!(f, "failed to parse {}:{}", path.display(), e),
+            SceneError::UnknownMesh(name) => {
+                write!(
+                    f,
+                    "an instance uses mesh {:?}, which is not in `meshes`",
+                    name
+                )
+            }
+            SceneError::Mesh(path, e) => {
+                write!(f, "failed to load mesh {}: {}", path.display(), e)
+            }
+            SceneError::UnknownTexture(name) => {
+                write!(
+                    f,
+                    "an instance uses texture {:?}, which is not in `textures`",
+                    name
+                )
+            }
+            SceneError::Texture(path, e) => {
+                write!(f, "failed to load texture {}: {}", path.display(), e)
+            }
+            SceneError::Environment(path, e) => {
+                write!(f, "failed to load environment {}: {}", path.display(), e)
+            }
+            SceneError::UnknownPart(mesh, part) => {
+                write!(f, "mesh {:?} has no part called {:?}", mesh, part)
+            }
+            SceneError::Animation(index, problem) => {
+                write!(f, "instance {} has a broken animation: {}", index, problem)
+            }
+            SceneError::Light(index, problem) => {
+                write!(f, "light {} is broken: {}", index, problem)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl Scene {
+    /// Finds, reads and validates the scene at `path`, and finds its meshes and textures.
+    pub fn load(assets: &Assets, path: &Path) -> Result<Scene, SceneError> {
+        let asset = assets.resolve(path).map_err(SceneError::Asset)?;
+        let source = asset
+            .read_to_string()
+            .map_err(|e| SceneError::Io(asset.path().to_path_buf(), e))?;
+        // Optional fields like `animation` can be written without wrapping them in `Some(...)`.
+        let mut scene: Scene = ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_str(&source)
+            .map_err(|e| SceneError::Parse(asset.path().to_path_buf(), e))?;
+
+        if let Some(instance) = scene
+            .instances
+            .iter()
+            .find(|instance| !scene.mesh_paths.contains_key(&instance.mesh))
+        {
+            return Err(SceneError::UnknownMesh(instance.mesh.clone()));
+        }
+        if let Some(texture) = scene
+            .instances
+            .iter()
+            .flat_map(|instance| instance.texture.iter().chain(&instance.normal_map))
+            .find(|texture| !scene.texture_paths.contains_key(*texture))
+        {
+            return Err(SceneError::UnknownTexture(texture.clone()));
+        }
+
+        for (index, instance) in scene.instances.iter().enumerate() {
+            for track in instance.animation.iter().flat_map(Animation::tracks) {
+                track
+                    .validate()
+                    .map_err(|problem| SceneError::Animation(index, problem))?;
+            }
+        }
+
+        for (index, light) in scene.lights.iter().enumerate() {
+            light
+                .validate()
+                .map_err(|problem| SceneError::Light(index, problem))?;
+        }
+
+        // Relative to the path the scene was asked for rather than where it was found, so that a
+        // scene on disk can still use the built-in meshes.
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for (name, mesh_path) in &scene.mesh_paths {
+            let mesh = assets
+                .resolve(&base.join(mesh_path))
+                .map_err(SceneError::Asset)?;
+            scene.meshes.insert(name.clone(), mesh);
+        }
+        for (name, texture_path) in &scene.texture_paths {
+            let texture = assets
+                .resolve(&base.join(texture_path))
+                .map_err(SceneError::Asset)?;
+            scene.textures.insert(name.clone(), texture);
+        }
+        if let Some(environment) = &mut scene.environment {
+            let image = assets
+                .resolve(&base.join(&environment.path))
+                .map_err(SceneError::Asset)?;
+            environment.image = Some(image);
+        }
+        scene.file = asset.file().map(Path::to_path_buf);
+
+        Ok(scene)
+    }
+
+    /// The mesh of the first instance in a grid facing the camera, under the same lights. Metallic
+    /// goes from 0 to 1 left to right and roughness from 0 to 1 bottom to top, everything else
+    /// comes from the instance. Shows what the two do on the physically based level.
+    pub fn material_grid(&self) -> Scene {
+        let first = self.instances.first();
+        let step = |index: usize| index as f32 / (GRID_SIZE - 1) as f32;
+        let offset = |index: usize| (index as f32 - (GRID_SIZE - 1) as f32 / 2.0) * GRID_SPACING;
+        let instances = (0..GRID_SIZE)
+            .flat_map(|row| (0..GRID_SIZE).map(move |column| (row, column)))
+            .filter_map(|(row, column)| {
+                Some(Instance {
+                    position: [offset(column), offset(row), 0.0],
+                    rotation: [0.0; 3],
+                    scale: Scale::default(),
+                    metallic: Some(step(column)),
+                    roughness: Some(step(row)),
+                    animation: None,
+                    ..first?.clone()
+                })
+            })
+            .collect();
+
+        Scene {
+            camera: CameraStart {
+                eye: [0.0, 0.0, GRID_SIZE as f32 * GRID_SPACING * 0.7],
+                yaw: 0.0,
+                pitch: 0.0,
+            },
+            clear_colour: self.clear_colour,
+            lights: self.lights.clone(),
+            swarm: None,
+            ground: None,
+            environment: self.environment.clone(),
+            mesh_paths: self.mesh_paths.clone(),
+            meshes: self.meshes.clone(),
+            texture_paths: self.texture_paths.clone(),
+            textures: self.textures.clone(),
+            instances,
+            file: self.file.clone(),
+        }
+    }
+
+    /// Every file on disk the scene reads, including the scene file. The files its models read in
+    /// turn are only known once they are loaded, see `Renderer::model_files`.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.file
+            .as_deref()
+            .into_iter()
+            .chain(self.meshes.values().filter_map(Asset::file))
+            .chain(self.textures.values().filter_map(Asset::file))
+            .chain(
+                self.environment
+                    .iter()
+                    .filter_map(|environment| environment.image.as_ref()?.file()),
+            )
+            .map(Path::to_path_buf)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    const MONKE: &str = r#"meshes: { "monke": "monke.obj" }"#;
+
+    /// Loads `source` as `scene.ron` from an asset root of its own.
+    fn load_source(name: &str, source: &[u8]) -> Result<Scene, SceneError> {
+        let dir = env::temp_dir().join(format!(
+            "rendering_demo_scene_{}_{}",
+            name,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("scene.ron"), source).unwrap();
+        let scene = Scene::load(&Assets::new(Some(dir.clone())), Path::new("scene.ron"));
+        fs::remove_dir_all(dir).unwrap();
+        scene
+    }
+
+    fn load_repo_scene(name: &str) -> Scene {
+        let assets = Assets::new(Some(PathBuf::from(env!("CARGO_MANIFEST_DIR"))));
+        Scene::load(&assets, &Path::new("scenes").join(name)).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn default_scene_test() {
+        let scene = load_repo_scene("default.ron");
+
+        assert_eq!(scene.instances.len(), 8);
+        assert!(matches!(
+            scene.lights[..],
+            [
+                Light::Directional { .. },
+                Light::Point { .. },
+                Light::Spot { .. }
+            ]
+        ));
+        assert_eq!(scene.ground.as_ref().unwrap().height, Some(-0.4));
+        assert_eq!(scene.environment.as_ref().unwrap().intensity, 0.5);
+        assert_eq!(scene.instances[1].texture.as_deref(), Some("checker"));
+        assert_eq!(scene.instances[1].normal_map.as_deref(), Some("tiles"));
+        let animation = scene.instances[2].animation.as_ref().unwrap();
+        assert!(animation.looping);
+        assert_eq!(animation.tracks().count(), 1);
+
+        // Everything is found on disk, next to the scene.
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let files = scene.files();
+        assert_eq!(files.len(), 5);
+        assert!(files.contains(&root.join("scenes/default.ron")));
+        assert!(files.contains(&root.join("monke.obj")));
+        assert!(files.contains(&root.join("textures/checker.png")));
+        assert!(files.contains(&root.join("environments/sky.hdr")));
+    }
+
+    #[test]
+    fn transforms_scene_test() {
+        let scene = load_repo_scene("transforms.ron");
+
+        assert_eq!(scene.camera.eye, [0.0, 1.0, 6.0]);
+        assert_eq!(scene.camera.pitch, 10.0);
+        assert_eq!(scene.clear_colour, default_clear_colour());
+        assert_eq!(scene.instances.len(), 4);
+        assert_eq!(scene.instances[0].rotation, [0.0, 45.0, 0.0]);
+        assert_eq!(scene.instances[0].scale.per_axis(), [1.0; 3]);
+        assert_eq!(scene.instances[2].scale.per_axis(), [2.0, 0.5, 1.0]);
+        let ground = scene.ground.as_ref().unwrap();
+        assert_eq!((ground.height, ground.colour), (None, grey()));
+    }
+
+    #[test]
+    fn lights_scene_test() {
+        let scene = load_repo_scene("lights.ron");
+
+        let swarm = scene.swarm.as_ref().unwrap();
+        assert_eq!(swarm.count, 1000);
+        assert_eq!(swarm.lights_at(0.0).count(), 1000);
+        assert_eq!(swarm.range, 2.5);
+        assert_eq!(scene.instances.len(), 25);
+        assert!(matches!(
+            scene.lights[..],
+            [Light::Directional { intensity, .. }] if intensity == 0.05
+        ));
+    }
+
+    #[test]
+    fn defaults_test() {
+        let source = format!(
+            "(
+                {},
+                lights: [
+                    Point(position: (0.0, 1.0, 0.0)),
+                    Spot(position: (0.0, 1.0, 0.0), direction: (0.0, -1.0, 0.0)),
+                ],
+                instances: [(mesh: \"monke\")],
+            )",
+            MONKE
+        );
+        let scene = load_source("defaults", source.as_bytes()).unwrap();
+
+        assert_eq!(scene.camera.eye, [0.0, 0.0, 2.0]);
+        assert_eq!((scene.camera.yaw, scene.camera.pitch), (0.0, 0.0));
+        assert_eq!(scene.clear_colour, default_clear_colour());
+        assert!(scene.swarm.is_none() && scene.ground.is_none() && scene.environment.is_none());
+        assert!(matches!(
+            scene.lights[0],
+            Light::Point { colour, intensity, range, .. }
+                if colour == white() && intensity == 1.0 && range == 10.0
+        ));
+        assert!(matches!(
+            scene.lights[1],
+            Light::Spot { inner_angle, outer_angle, .. }
+                if inner_angle == 20.0 && outer_angle == 30.0
+        ));
+
+        let instance = &scene.instances[0];
+        assert_eq!(instance.position, [0.0; 3]);
+        assert_eq!(instance.rotation, [0.0; 3]);
+        assert_eq!(instance.scale.per_axis(), [1.0; 3]);
+        assert_eq!(instance.colour, white());
+        assert!(instance.specular.is_none() && instance.animation.is_none());
+        // The mesh isn't next to the scene, so the built-in one is used.
+        assert!(matches!(
+            scene.meshes["monke"],
+            Asset::Embedded("monke.obj", _)
+        ));
+        assert_eq!(scene.files().len(), 1);
+    }
+
+    #[test]
+    fn asset_error_test() {
+        let error = load_source(
+            "asset",
+            br#"(meshes: { "monke": "missing.obj" }, instances: [])"#,
+        );
+        assert!(
+            matches!(error, Err(SceneError::Asset(ref e)) if e.path == Path::new("missing.obj")),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn io_error_test() {
+        let error = load_source("io", b"(instances: [\xff])");
+        assert!(matches!(error, Err(SceneError::Io(..))), "{:?}", error);
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let error = load_source("parse", b"(instances: [(mesh: )])");
+        assert!(matches!(error, Err(SceneError::Parse(..))), "{:?}", error);
+        let error = load_source("parse_missing", b"(meshes: {})");
+        assert!(matches!(error, Err(SceneError::Parse(..))), "{:?}", error);
+    }
+
+    #[test]
+    fn unknown_mesh_test() {
+        let source = format!("({}, instances: [(mesh: \"cube\")])", MONKE);
+        let error = load_source("unknown_mesh", source.as_bytes());
+        assert!(
+            matches!(error, Err(SceneError::UnknownMesh(ref name)) if name == "cube"),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn unknown_texture_test() {
+        let source = format!(
+            "({}, textures: {{ \"checker\": \"textures/checker.png\" }}, \
+             instances: [(mesh: \"monke\", texture: \"checker\", normal_map: \"bricks\")])",
+            MONKE
+        );
+        let error = load_source("unknown_texture", source.as_bytes());
+        assert!(
+            matches!(error, Err(SceneError::UnknownTexture(ref name)) if name == "bricks"),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn animation_error_test() {
+        for (keys, problem) in [
+            (
+                "[(1.0, (0.0, 0.0, 0.0)), (0.0, (1.0, 1.0, 1.0))]",
+                "the keys of a track are not sorted by time",
+            ),
+            ("[(NaN, (0.0, 0.0, 0.0))]", "a key time is not a number"),
+            ("[]", "a track has no keys"),
+        ] {
+            let source = format!(
+                "({}, instances: [(mesh: \"monke\"), \
+                 (mesh: \"monke\", animation: (scale: (keys: {})))])",
+                MONKE, keys
+            );
+            let error = load_source("animation", source.as_bytes());
+            assert!(
+                matches!(error, Err(SceneError::Animation(1, p)) if p == problem),
+                "{:?}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn light_error_test() {
+        for (light, problem) in [
+            (
+                "Directional(direction: (0.0, 0.0, 0.0))",
+                "the direction is zero",
+            ),
+            (
+                "Point(position: (0.0, 0.0, 0.0), range: 0.0)",
+                "the range has to be positive",
+            ),
+            (
+                "Spot(position: (0.0, 0.0, 0.0), direction: (0.0, 0.0, 0.0))",
+                "the direction is zero",
+            ),
+            (
+                "Spot(position: (0.0, 0.0, 0.0), direction: (0.0, -1.0, 0.0), inner_angle: 40.0)",
+                "the angles need 0 <= inner_angle <= outer_angle < 90",
+            ),
+            (
+                "Spot(position: (0.0, 0.0, 0.0), direction: (0.0, -1.0, 0.0), outer_angle: 90.0)",
+                "the angles need 0 <= inner_angle <= outer_angle < 90",
+            ),
+        ] {
+            let source = format!(
+                "({}, lights: [Directional(direction: (0.0, 1.0, 0.0)), {}], instances: [])",
+                MONKE, light
+            );
+            let error = load_source("light", source.as_bytes());
+            assert!(
+                matches!(error, Err(SceneError::Light(1, p)) if p == problem),
+                "{:?}",
+                error
+            );
+        }
+    }
+
+    /// The variants the renderer reports once it loads what the scene points at.
+    #[test]
+    fn loading_error_test() {
+        let image_error = || image::ImageError::IoError(io::Error::other("broken"));
+        assert_eq!(
+            SceneError::Mesh(PathBuf::from("cube.obj"), MeshError::UnknownFormat).to_string(),
+            "failed to load mesh cube.obj: unknown format, expected .obj, .gltf or .glb"
+        );
+        assert_eq!(
+            SceneError::Texture(PathBuf::from("checker.png"), image_error()).to_string(),
+            "failed to load texture checker.png: broken"
+        );
+        assert_eq!(
+            SceneError::Environment(PathBuf::from("sky.hdr"), image_error()).to_string(),
+            "failed to load environment sky.hdr: broken"
+        );
+        assert_eq!(
+            SceneError::UnknownPart("monke".to_string(), "ear".to_string()).to_string(),
+            "mesh \"monke\" has no part called \"ear\""
+        );
+    }
+
+    #[test]
+    fn material_grid_test() {
+        let source = format!(
+            "(
+                {},
+                lights: [Directional(direction: (0.0, 1.0, 0.0))],
+                swarm: (count: 10, min: (0.0, 0.0, 0.0), max: (1.0, 1.0, 1.0)),
+                instances: [
+                    (
+                        mesh: \"monke\", position: (5.0, 5.0, 5.0), scale: 3.0,
+                        colour: (1.0, 0.0, 0.0),
+                        animation: (position: (keys: [(0.0, (0.0, 0.0, 0.0))])),
+                    ),
+                    (mesh: \"monke\"),
+                ],
+            )",
+            MONKE
+        );
+        let scene = load_source("material_grid", source.as_bytes()).unwrap();
+        let grid = scene.material_grid();
+
+        assert_eq!(grid.instances.len(), GRID_SIZE * GRID_SIZE);
+        assert_eq!(grid.lights.len(), 1);
+        assert!(grid.swarm.is_none());
+        let first = &grid.instances[0];
+        let last = &grid.instances[GRID_SIZE * GRID_SIZE - 1];
+        assert_eq!((first.metallic, first.roughness), (Some(0.0), Some(0.0)));
+        assert_eq!((last.metallic, last.roughness), (Some(1.0), Some(1.0)));
+        // Metallic along the row, roughness up the columns, centred on the origin.
+        assert_eq!(grid.instances[1].metallic, Some(1.0 / 6.0));
+        assert_eq!(grid.instances[GRID_SIZE].roughness, Some(1.0 / 6.0));
+        assert_eq!(first.position, [-9.0, -9.0, 0.0]);
+        assert_eq!(last.position, [9.0, 9.0, 0.0]);
+        for instance in &grid.instances {
+            assert_eq!(instance.colour, [1.0, 0.0, 0.0]);
+            assert_eq!(instance.scale.per_axis(), [1.0; 3]);
+            assert!(instance.animation.is_none());
+        }
+
+        let empty = load_source(
+            "material_grid_empty",
+            format!("({}, instances: [])", MONKE).as_bytes(),
+        )
+        .unwrap();
+        assert!(empty.material_grid().instances.is_empty());
+    }
+}
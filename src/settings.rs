@@ -1,4 +1,6 @@
-#[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone)]
+use std::path::PathBuf;
+
+#[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Debug)]
 pub enum Levels {
     // default
     ONE = 1,
@@ -10,4 +12,270 @@ pub enum Levels {
     FOUR = 4,
     // lighting
     FIVE = 5,
+    // shadows
+    SIX = 6,
+    // specular
+    SEVEN = 7,
+    // physically based
+    EIGHT = 8,
+    // clustered lighting
+    NINE = 9,
+    // textures
+    TEN = 10,
+    // image based lighting
+    ELEVEN = 11,
+    // hdr and tonemapping
+    TWELVE = 12,
+}
+
+impl Levels {
+    pub const ALL: [Levels; 12] = [
+        Levels::ONE,
+        Levels::TWO,
+        Levels::THREE,
+        Levels::FOUR,
+        Levels::FIVE,
+        Levels::SIX,
+        Levels::SEVEN,
+        Levels::EIGHT,
+        Levels::NINE,
+        Levels::TEN,
+        Levels::ELEVEN,
+        Levels::TWELVE,
+    ];
+
+    pub fn from_number(number: u32) -> Option<Levels> {
+        Levels::ALL
+            .into_iter()
+            .find(|level| *level as u32 == number)
+    }
+}
+
+/// How the triangles are filled in.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DrawMode {
+    Solid,
+    /// Only the edges of the triangles.
+    Wireframe,
+    /// Shaded, with the edges drawn on top.
+    Overlay,
+}
+
+impl DrawMode {
+    pub fn next(self) -> Self {
+        match self {
+            DrawMode::Solid => DrawMode::Wireframe,
+            DrawMode::Wireframe => DrawMode::Overlay,
+            DrawMode::Overlay => DrawMode::Solid,
+        }
+    }
+}
+
+/// The curve that squeezes the HDR colour of the scene into the image, from level 12 on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Tonemapper {
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// `colour / (1 + colour)`, which never quite reaches white.
+    Reinhard,
+    /// John Hable's curve from Uncharted 2.
+    Filmic,
+}
+
+impl Tonemapper {
+    pub fn next(self) -> Self {
+        match self {
+            Tonemapper::Aces => Tonemapper::Reinhard,
+            Tonemapper::Reinhard => Tonemapper::Filmic,
+            Tonemapper::Filmic => Tonemapper::Aces,
+        }
+    }
+}
+
+/// Command line options.
+///
+/// `--scene <path>` picks the scene file, `scenes/default.ron` by default.
+/// `--assets <dir>` is searched first for the scene and the meshes, see `assets`.
+/// `--headless` renders the levels offscreen and writes them as PNGs instead of opening a window.
+/// `--output <dir>` is where the PNGs go, `--size <width>x<height>` their resolution and
+/// `--level <n>` limits the render to a single level.
+/// `--dev-shaders` recompiles the shaders from the source tree whenever they are saved.
+/// `--material-grid` starts with the material grid instead of the scene, see
+/// `Scene::material_grid`.
+pub struct Args {
+    pub scene: PathBuf,
+    pub assets: Option<PathBuf>,
+    pub headless: bool,
+    pub output: PathBuf,
+    pub size: [u32; 2],
+    pub levels: Vec<Levels>,
+    pub dev_shaders: bool,
+    pub material_grid: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            scene: PathBuf::from("scenes/default.ron"),
+            assets: None,
+            headless: false,
+            output: PathBuf::from("renders"),
+            size: [1280, 720],
+            levels: Levels::ALL.to_vec(),
+            dev_shaders: false,
+            material_grid: false,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Self {
+        match Self::parse_from(std::env::args().skip(1)) {
+            Ok(args) => args,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    fn parse_from(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--scene" => parsed.scene = PathBuf::from(value()?),
+                "--assets" => parsed.assets = Some(PathBuf::from(value()?)),
+                "--headless" => parsed.headless = true,
+                "--output" => parsed.output = PathBuf::from(value()?),
+                "--size" => {
+                    let size = value()?;
+                    parsed.size = size
+                        .split_once('x')
+                        .and_then(|(w, h)| Some([w.parse().ok()?, h.parse().ok()?]))
+                        .filter(|[w, h]| *w > 0 && *h > 0)
+                        .ok_or_else(|| format!("invalid size {:?}, expected WIDTHxHEIGHT", size))?;
+                }
+                "--level" => {
+                    let number = value()?;
+                    let level = number
+                        .parse()
+                        .ok()
+                        .and_then(Levels::from_number)
+                        .ok_or_else(|| format!("unknown level {:?}", number))?;
+                    parsed.levels = vec![level];
+                }
+                "--dev-shaders" => parsed.dev_shaders = true,
+                "--material-grid" => parsed.material_grid = true,
+                _ => return Err(format!("unknown argument {:?}", arg)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse_from(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn defaults_test() {
+        let args = parse(&[]).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(args.scene, PathBuf::from("scenes/default.ron"));
+        assert_eq!(args.assets, None);
+        assert!(!args.headless);
+        assert_eq!(args.levels, Levels::ALL);
+        assert!(!args.dev_shaders);
+        assert!(!args.material_grid);
+    }
+
+    #[test]
+    fn headless_test() {
+        let args = parse(&["--headless"]).unwrap_or_else(|e| panic!("{}", e));
+        assert!(args.headless);
+        assert_eq!(args.output, PathBuf::from("renders"));
+        assert_eq!(args.size, [1280, 720]);
+        assert_eq!(args.levels, Levels::ALL);
+
+        // The order doesn't matter.
+        for args in [
+            [
+                "--headless",
+                "--output",
+                "out",
+                "--size",
+                "64x32",
+                "--level",
+                "12",
+            ],
+            [
+                "--level",
+                "12",
+                "--size",
+                "64x32",
+                "--output",
+                "out",
+                "--headless",
+            ],
+        ] {
+            let args = parse(&args).unwrap_or_else(|e| panic!("{}", e));
+            assert!(args.headless);
+            assert_eq!(args.output, PathBuf::from("out"));
+            assert_eq!(args.size, [64, 32]);
+            assert_eq!(args.levels, [Levels::TWELVE]);
+        }
+
+        let args = parse(&["--headless", "--material-grid", "--assets", "dir"])
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert!(args.headless && args.material_grid);
+        assert_eq!(args.assets, Some(PathBuf::from("dir")));
+
+        // The last one counts.
+        let args = parse(&["--headless", "--level", "3", "--level", "5"])
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(args.levels, [Levels::FIVE]);
+    }
+
+    #[test]
+    fn unknown_test() {
+        assert_eq!(
+            parse(&["--fullscreen"]).err(),
+            Some("unknown argument \"--fullscreen\"".to_string())
+        );
+        assert_eq!(
+            parse(&["--headless", "scene.ron"]).err(),
+            Some("unknown argument \"scene.ron\"".to_string())
+        );
+        assert_eq!(
+            parse(&["--level", "13"]).err(),
+            Some("unknown level \"13\"".to_string())
+        );
+        assert_eq!(
+            parse(&["--level", "one"]).err(),
+            Some("unknown level \"one\"".to_string())
+        );
+        for size in ["64", "64x", "0x32", "64x-1", "64*32"] {
+            assert_eq!(
+                parse(&["--headless", "--size", size]).err(),
+                Some(format!("invalid size {:?}, expected WIDTHxHEIGHT", size))
+            );
+        }
+    }
+
+    #[test]
+    fn missing_value_test() {
+        for flag in ["--scene", "--assets", "--output", "--size", "--level"] {
+            assert_eq!(
+                parse(&["--headless", flag]).err(),
+                Some(format!("missing value for {}", flag))
+            );
+        }
+    }
 }
@@ -0,0 +1,7 @@
+#version 450
+
+// One triangle that covers the whole screen, drawn from three vertices without a vertex buffer.
+void main() {
+    vec2 corner = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    gl_Position = vec4(corner, 0.0, 1.0);
+}
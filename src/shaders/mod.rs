@@ -1,6 +1,8 @@
 use bytemuck::{Pod, Zeroable};
 use vulkano::impl_vertex;
 
+pub mod runtime;
+
 pub mod vs {
     vulkano_shaders::shader! {
         ty: "vertex",
@@ -25,21 +27,121 @@ pub mod fs {
     }
 }
 
+pub mod shadow_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/shaders/shadow.vert",
+        types_meta: {
+            use bytemuck::{Zeroable, Pod};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+pub mod cluster_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/cluster.comp",
+        types_meta: {
+            use bytemuck::{Zeroable, Pod};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+pub mod sky_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/shaders/sky.vert",
+        types_meta: {
+            use bytemuck::{Zeroable, Pod};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+pub mod sky_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/sky.frag",
+    }
+}
+
+pub mod fullscreen_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/shaders/fullscreen.vert",
+    }
+}
+
+pub mod tonemap_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/tonemap.frag",
+        types_meta: {
+            use bytemuck::{Zeroable, Pod};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+pub mod histogram_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/histogram.comp",
+    }
+}
+
+pub mod exposure_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/exposure.comp",
+        types_meta: {
+            use bytemuck::{Zeroable, Pod};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
 pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
+    /// Texture coordinates, with v going down the image.
+    pub uv: [f32; 2],
+    /// Along the surface in the direction u grows. `w` is 1 or -1 and says which way the
+    /// bitangent, `cross(normal, tangent) * w`, points.
+    pub tangent: [f32; 4],
 }
 
-impl_vertex!(Vertex, position, normal);
+impl_vertex!(Vertex, position, normal, uv, tangent);
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
 pub struct MonkeInstance {
-    pub transform: [f32; 3],
+    pub model: [[f32; 4]; 4],
+    /// Inverse transpose of the upper 3x3 of `model`.
+    pub normal_matrix: [[f32; 3]; 3],
     pub colour: [f32; 3],
-    pub scale: f32,
+    pub specular: [f32; 3],
+    /// Shininess, metallic and roughness, in one attribute so that the vertex and the instance
+    /// together fit in the 16 attribute locations every device supports.
+    pub surface: [f32; 3],
+    pub emissive: [f32; 3],
 }
 
-impl_vertex!(MonkeInstance, transform, colour, scale);
+impl_vertex!(
+    MonkeInstance,
+    model,
+    normal_matrix,
+    colour,
+    specular,
+    surface,
+    emissive
+);
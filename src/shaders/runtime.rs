@@ -0,0 +1,83 @@
+//! Compiles the GLSL sources while the demo runs, so that `--dev-shaders` can pick up edits
+//! without a rebuild. Only the shader code is reloaded: the uniform blocks and vertex inputs are
+//! still the ones compiled into the binary, so changing those needs a rebuild.
+
+use shaderc::{CompileOptions, Compiler, EnvVersion, ShaderKind, TargetEnv};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vulkano::device::Device;
+use vulkano::shader::{ShaderCreationError, ShaderModule};
+
+/// The same files `vs` and `fs` are built from. They are looked up in the source tree the binary
+/// was built from, dev mode is meant to be run from a checkout.
+pub const VERTEX: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/shader.vert");
+pub const FRAGMENT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/shader.frag");
+
+pub fn sources() -> [PathBuf; 2] {
+    [PathBuf::from(VERTEX), PathBuf::from(FRAGMENT)]
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(PathBuf, io::Error),
+    Compiler,
+    Compile(shaderc::Error),
+    Module(PathBuf, ShaderCreationError),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            ShaderError::Compiler => write!(f, "failed to initialise the shader compiler"),
+            // shaderc already prefixes every message with the file name and line.
+            ShaderError::Compile(e) => write!(f, "{}", e),
+            ShaderError::Module(path, e) => {
+                write!(f, "failed to load {}: {}", path.display(), e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// Compiles the vertex and fragment shader from disk.
+pub fn compile(
+    device: &Arc<Device>,
+) -> Result<(Arc<ShaderModule>, Arc<ShaderModule>), ShaderError> {
+    let compiler = Compiler::new().ok_or(ShaderError::Compiler)?;
+    let vs = compile_file(&compiler, device, Path::new(VERTEX), ShaderKind::Vertex)?;
+    let fs = compile_file(&compiler, device, Path::new(FRAGMENT), ShaderKind::Fragment)?;
+    Ok((vs, fs))
+}
+
+fn compile_file(
+    compiler: &Compiler,
+    device: &Arc<Device>,
+    path: &Path,
+    kind: ShaderKind,
+) -> Result<Arc<ShaderModule>, ShaderError> {
+    let source = fs::read_to_string(path).map_err(|e| ShaderError::Io(path.to_path_buf(), e))?;
+
+    let mut options = CompileOptions::new().ok_or(ShaderError::Compiler)?;
+    options.set_target_env(TargetEnv::Vulkan, EnvVersion::Vulkan1_0 as u32);
+    let artifact = compiler
+        .compile_into_spirv(
+            &source,
+            kind,
+            &path.display().to_string(),
+            "main",
+            Some(&options),
+        )
+        .map_err(ShaderError::Compile)?;
+    if artifact.get_num_warnings() > 0 {
+        eprintln!("{}", artifact.get_warning_messages());
+    }
+
+    // SAFETY: shaderc only hands out SPIR-V that passed validation.
+    unsafe { ShaderModule::from_words(device.clone(), artifact.as_binary()) }
+        .map_err(|e| ShaderError::Module(path.to_path_buf(), e))
+}
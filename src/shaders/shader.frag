@@ -2,22 +2,319 @@
 
 layout (location = 0) in vec3 v_normal;
 layout (location = 1) in vec3 v_colour;
+layout (location = 2) in vec4 v_light_space;
+layout (location = 3) in vec3 v_position;
+layout (location = 4) flat in vec3 v_specular;
+layout (location = 5) flat in float v_shininess;
+layout (location = 6) flat in float v_metallic;
+layout (location = 7) flat in float v_roughness;
+layout (location = 8) flat in vec3 v_emissive;
+layout (location = 9) in vec2 v_uv;
+// The sign in w says whether the bitangent is flipped.
+layout (location = 10) in vec4 v_tangent;
 layout (location = 0) out vec4 f_colour;
 
-const vec3 LIGHT = vec3(0.0, 1.0, 1.0);
+// Set for the edges drawn over the shaded mesh in the wireframe overlay.
+layout (constant_id = 0) const bool overlay = false;
 
 layout (set = 0, binding = 1) uniform Data {
+    // world space
+    vec4 camera_position;
+    vec4 camera_forward;
+    // in pixels
+    vec2 screen_size;
+    uint light_count;
+    // index of the light that casts the shadows
+    uint shadow_light;
+    // view space distance to the near and far plane
+    float near;
+    float far;
     bool lighting;
+    bool shadows;
+    bool specular;
+    bool physically_based;
+    bool clustered;
+    bool textured;
+    bool normal_mapping;
+    // ambient light from the environment map instead of the constant
+    bool image_based;
+    // shows how many lights every cluster lists instead of the lighting
+    bool cluster_heatmap;
 } uniforms;
 
+// Light that reaches everything from everywhere, standing in for the light bouncing around the
+// scene. Keeps the sides facing away from the light from going black.
+const float ambient = 0.1;
+
+const float PI = 3.14159265;
+
+const uint DIRECTIONAL = 0u;
+const uint POINT = 1u;
+const uint SPOT = 2u;
+
+struct Light {
+    // world space
+    vec4 position;
+    // world space, points towards a directional light and the way a spot light shines
+    vec4 direction;
+    // times the intensity
+    vec4 colour;
+    float range;
+    // cosines of the cone angles of a spot light
+    float cos_inner;
+    float cos_outer;
+    uint kind;
+};
+
+layout (set = 0, binding = 3) readonly buffer Lights {
+    Light lights[];
+};
+
+// Filled in by cluster.comp, see there.
+const uvec3 CLUSTER_GRID = uvec3(16, 9, 24);
+const uint MAX_LIGHTS_PER_CLUSTER = 128u;
+
+layout (set = 0, binding = 4) readonly buffer ClusterLightCounts {
+    uint cluster_light_counts[];
+};
+
+layout (set = 0, binding = 5) readonly buffer ClusterLightIndices {
+    uint cluster_light_indices[];
+};
+
+// Light counts at or above this are red in the heatmap.
+const float HEATMAP_MAX = 32.0;
+
+// Depth from the light, compared against on every lookup.
+layout (set = 0, binding = 2) uniform sampler2DShadow shadow_map;
+
+// The environment, see environment.rs. The prefiltered map has one mip level for every step of
+// roughness, from a mirror on the first to chalk on the last.
+layout (set = 0, binding = 6) uniform samplerCube irradiance_map;
+layout (set = 0, binding = 7) uniform samplerCube prefiltered_map;
+layout (set = 0, binding = 8) uniform sampler2D brdf_lut;
+
+// Multiplies the colour of the instance. White for meshes without a texture.
+layout (set = 1, binding = 0) uniform sampler2D albedo_texture;
+// Normals in tangent space. A flat one for meshes without a normal map.
+layout (set = 1, binding = 1) uniform sampler2D normal_map;
+// Roughness in green and metallic in blue, like glTF, multiplying the ones of the instance. White
+// for meshes without one.
+layout (set = 1, binding = 2) uniform sampler2D metallic_roughness_texture;
+// Multiplies the specular colour of the instance. White for meshes without one.
+layout (set = 1, binding = 3) uniform sampler2D specular_texture;
+
+// The material at the fragment, the instance's with the textures applied. Set at the start of
+// `main`.
+vec3 surface_specular;
+float surface_metallic;
+float surface_roughness;
+
+// How much of the light reaches the fragment, from 0 in full shadow to 1. Averages a 3x3 block of
+// lookups around it, so the shadow edges come out soft instead of blocky.
+float light_reaching() {
+    vec3 coords = v_light_space.xyz / v_light_space.w;
+    vec2 uv = coords.xy * 0.5 + 0.5;
+    vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadow_map, vec3(uv + vec2(x, y) * texel, coords.z));
+        }
+    }
+    return lit / 9.0;
+}
+
+// `normal` tilted the way the normal map says. Meshes without texture coordinates have no tangents,
+// their normal stays as it is.
+vec3 mapped_normal(vec3 normal) {
+    // Interpolation bends the tangent away from the normal, straighten it again.
+    vec3 tangent = v_tangent.xyz - dot(v_tangent.xyz, normal) * normal;
+    if (dot(tangent, tangent) < 1e-8) {
+        return normal;
+    }
+    tangent = normalize(tangent);
+    vec3 bitangent = cross(normal, tangent) * v_tangent.w;
+    vec3 tilted = texture(normal_map, v_uv).xyz * 2.0 - 1.0;
+    return normalize(mat3(tangent, bitangent, normal) * tilted);
+}
+
+// The cluster the fragment is in, the same way cluster.comp numbers them.
+uint cluster_index() {
+    uvec2 tile = uvec2(gl_FragCoord.xy / uniforms.screen_size * vec2(CLUSTER_GRID.xy));
+    float depth = dot(v_position - uniforms.camera_position.xyz, uniforms.camera_forward.xyz);
+    float slice = log(depth / uniforms.near) / log(uniforms.far / uniforms.near);
+    uvec3 cluster = min(
+        uvec3(tile, uint(max(slice * float(CLUSTER_GRID.z), 0.0))),
+        CLUSTER_GRID - 1u
+    );
+    return cluster.x + CLUSTER_GRID.x * (cluster.y + CLUSTER_GRID.y * cluster.z);
+}
+
+// Blue for no lights through green to red for `HEATMAP_MAX` and more.
+vec3 heat(uint count) {
+    float t = clamp(float(count) / HEATMAP_MAX, 0.0, 1.0);
+    return clamp(vec3(2.0 * t - 0.5, 1.5 - abs(2.0 * t - 1.0) * 2.0, 1.5 - 2.0 * t), 0.0, 1.0);
+}
+
+// How much of `light` arrives at the fragment, and from where.
+vec3 incoming(Light light, out vec3 to_light) {
+    if (light.kind == DIRECTIONAL) {
+        to_light = normalize(light.direction.xyz);
+        return light.colour.rgb;
+    }
+
+    vec3 offset = light.position.xyz - v_position;
+    float distance = length(offset);
+    to_light = offset / distance;
+    // Falls off with the square of the distance, smoothed down to nothing at the range.
+    float window = clamp(1.0 - pow(distance / light.range, 4.0), 0.0, 1.0);
+    float attenuation = window * window / max(distance * distance, 0.0001);
+    if (light.kind == SPOT) {
+        float cos_angle = dot(-to_light, normalize(light.direction.xyz));
+        attenuation *= smoothstep(light.cos_outer, light.cos_inner, cos_angle);
+    }
+    return light.colour.rgb * attenuation;
+}
+
+// Diffuse and Blinn-Phong highlights.
+vec3 blinn_phong(vec3 albedo, vec3 normal, vec3 to_light, vec3 to_camera, vec3 light) {
+    // Facing away from the light is as dark as it gets, not darker.
+    float diffuse = max(dot(normal, to_light), 0.0);
+    vec3 halfway = normalize(to_light + to_camera);
+    // Without the check the highlight would bleed onto the side facing away from the light.
+    float specular = 0.0;
+    if (diffuse > 0.0) {
+        specular = pow(max(dot(normal, halfway), 0.0), max(v_shininess, 1.0));
+    }
+    return (diffuse * albedo + specular * surface_specular) * light;
+}
+
+// How many of the microfacets face along `halfway`, the GGX (Trowbridge-Reitz) distribution.
+float distribution(float n_dot_h, float roughness) {
+    float alpha = roughness * roughness;
+    float alpha_squared = alpha * alpha;
+    float d = n_dot_h * n_dot_h * (alpha_squared - 1.0) + 1.0;
+    return alpha_squared / (PI * d * d);
+}
+
+// How many of those are neither hidden from the light nor from the camera by the others, Smith's
+// method with the Schlick-GGX approximation for each direction.
+float geometry(float n_dot_v, float n_dot_l, float roughness) {
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+}
+
+// How much of the light is reflected rather than refracted, Schlick's approximation. `f0` is the
+// reflectance when looking straight at the surface.
+vec3 fresnel(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Cook-Torrance highlights over a Lambert diffuse that only gets the light the highlights leave.
+vec3 physically_based(vec3 albedo, vec3 normal, vec3 to_light, vec3 to_camera, vec3 light) {
+    vec3 halfway = normalize(to_light + to_camera);
+    float n_dot_l = max(dot(normal, to_light), 0.0);
+    // Kept off zero, the silhouette would divide by it.
+    float n_dot_v = max(dot(normal, to_camera), 0.0001);
+    float n_dot_h = max(dot(normal, halfway), 0.0);
+    // A perfectly smooth surface would have an infinitely small and bright highlight.
+    float roughness = clamp(surface_roughness, 0.04, 1.0);
+
+    // Dielectrics reflect about 4% head on and in white, metals reflect in their own colour.
+    vec3 f0 = mix(vec3(0.04), albedo, surface_metallic);
+    vec3 reflected = fresnel(max(dot(halfway, to_camera), 0.0), f0);
+    vec3 specular = distribution(n_dot_h, roughness) * geometry(n_dot_v, n_dot_l, roughness)
+        * reflected / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+    // The refracted light scatters around and comes back out as the diffuse colour, metals absorb
+    // it instead.
+    vec3 diffuse = (1.0 - reflected) * (1.0 - surface_metallic) * albedo / PI;
+
+    // The light is scaled by pi so that a white surface facing it comes out as bright as on the
+    // levels before.
+    return (diffuse + specular) * light * PI * n_dot_l;
+}
+
+// The light the environment map casts, split the same way as in `physically_based`: the diffuse
+// part from the irradiance, the highlights from the prefiltered reflections scaled by the BRDF
+// lookup table.
+vec3 environment_light(vec3 albedo, vec3 normal, vec3 to_camera) {
+    float n_dot_v = max(dot(normal, to_camera), 0.0001);
+    float roughness = clamp(surface_roughness, 0.0, 1.0);
+    vec3 f0 = mix(vec3(0.04), albedo, surface_metallic);
+    // Without a single halfway vector the reflectance goes by the normal, and rough surfaces don't
+    // brighten up as much at grazing angles.
+    vec3 reflected = f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+    vec3 diffuse = (1.0 - reflected) * (1.0 - surface_metallic) * albedo
+        * texture(irradiance_map, normal).rgb;
+
+    vec3 mirrored = reflect(-to_camera, normal);
+    float max_reflection_lod = float(textureQueryLevels(prefiltered_map) - 1);
+    vec3 prefiltered = textureLod(prefiltered_map, mirrored, roughness * max_reflection_lod).rgb;
+    vec2 brdf = texture(brdf_lut, vec2(n_dot_v, roughness)).rg;
+    return diffuse + prefiltered * (f0 * brdf.x + brdf.y);
+}
+
 void main() {
-    if (uniforms.lighting) {
-        float brightness = dot(normalize(v_normal), normalize(LIGHT));
-        vec3 dark_color = vec3(0.0, 0.0, 0.0);
-        vec3 regular_color = v_colour;
+    if (overlay) {
+        f_colour = vec4(1.0, 1.0, 1.0, 1.0);
+    } else if (uniforms.lighting) {
+        vec3 normal = normalize(v_normal);
+        if (uniforms.normal_mapping) {
+            normal = mapped_normal(normal);
+        }
+        vec3 to_camera = normalize(uniforms.camera_position.xyz - v_position);
+        vec3 albedo = v_colour;
+        surface_specular = v_specular;
+        surface_metallic = v_metallic;
+        surface_roughness = v_roughness;
+        if (uniforms.textured) {
+            albedo *= texture(albedo_texture, v_uv).rgb;
+            surface_specular *= texture(specular_texture, v_uv).rgb;
+            vec2 roughness_metallic = texture(metallic_roughness_texture, v_uv).gb;
+            surface_roughness *= roughness_metallic.x;
+            surface_metallic *= roughness_metallic.y;
+        }
+        vec3 colour = v_emissive;
+        if (uniforms.image_based) {
+            colour += environment_light(albedo, normal, to_camera);
+        } else if (uniforms.specular) {
+            colour += ambient * albedo;
+        }
+
+        // Either every light, or only the ones listed for the cluster.
+        uint count = uniforms.light_count;
+        uint first = 0u;
+        if (uniforms.clustered) {
+            uint cluster = cluster_index();
+            count = cluster_light_counts[cluster];
+            first = cluster * MAX_LIGHTS_PER_CLUSTER;
+        }
+        if (uniforms.cluster_heatmap) {
+            // Shaded a little, so that the shapes stay recognisable.
+            float facing = max(dot(normal, to_camera), 0.0);
+            f_colour = vec4(heat(count) * (0.5 + 0.5 * facing), 1.0);
+            return;
+        }
+
+        for (uint i = 0u; i < count; i++) {
+            uint index = uniforms.clustered ? cluster_light_indices[first + i] : i;
+            vec3 to_light;
+            vec3 light = incoming(lights[index], to_light);
+            if (uniforms.shadows && index == uniforms.shadow_light) {
+                light *= light_reaching();
+            }
 
-        f_colour = vec4(mix(dark_color, regular_color, brightness), 1.0);
+            if (uniforms.physically_based) {
+                colour += physically_based(albedo, normal, to_light, to_camera, light);
+            } else if (uniforms.specular) {
+                colour += blinn_phong(albedo, normal, to_light, to_camera, light);
+            } else {
+                colour += max(dot(normal, to_light), 0.0) * albedo * light;
+            }
+        }
+        f_colour = vec4(colour, 1.0);
     } else {
         f_colour = vec4(v_colour, 1.0);
     }
-}
\ No newline at end of file
+}
@@ -2,31 +2,59 @@
 
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec3 normal;
+layout(location = 14) in vec4 tangent;
+layout(location = 15) in vec2 uv;
 
-layout (location = 2) in vec3 transform;
-layout (location = 3) in vec3 colour;
-layout (location = 4) in float scale;
+layout (location = 2) in mat4 model;
+layout (location = 6) in mat3 normal_matrix;
+layout (location = 9) in vec3 colour;
+layout (location = 10) in vec3 specular;
+// shininess, metallic and roughness
+layout (location = 11) in vec3 surface;
+layout (location = 12) in vec3 emissive;
 
 layout(location = 0) out vec3 v_normal;
 layout(location = 1) out vec3 v_colour;
+layout(location = 2) out vec4 v_light_space;
+layout(location = 3) out vec3 v_position;
+layout(location = 4) flat out vec3 v_specular;
+layout(location = 5) flat out float v_shininess;
+layout(location = 6) flat out float v_metallic;
+layout(location = 7) flat out float v_roughness;
+layout(location = 8) flat out vec3 v_emissive;
+layout(location = 9) out vec2 v_uv;
+layout(location = 10) out vec4 v_tangent;
 
 layout(set = 0, binding = 0) uniform Data {
-    mat4 world;
     mat4 view;
     mat4 proj;
+    // world space to the clip space of the shadow map
+    mat4 light_space;
     bool lev_2;
 } uniforms;
 
 void main() {
     v_colour = colour;
-    vec3 transformed_position = (scale * position) + transform;
+    v_specular = specular;
+    v_shininess = surface.x;
+    v_metallic = surface.y;
+    v_roughness = surface.z;
+    v_emissive = emissive;
+    v_uv = uv;
+    vec4 transformed_position = model * vec4(position, 1.0);
+    v_light_space = uniforms.light_space * transformed_position;
+    v_position = transformed_position.xyz;
     if (uniforms.lev_2) {
-        mat4 worldview = uniforms.view * uniforms.world;
-        v_normal = transpose(inverse(mat3(worldview))) * normal;
-        gl_Position = uniforms.proj * worldview * vec4(transformed_position, 1.0);
+        v_normal = normal_matrix * normal;
+        // Tangents lie in the surface, so they move with it. A mirroring transform flips the
+        // bitangent.
+        mat3 to_world = mat3(model);
+        v_tangent = vec4(to_world * tangent.xyz, tangent.w * sign(determinant(to_world)));
+        gl_Position = uniforms.proj * uniforms.view * transformed_position;
     }
     else {
         v_normal = normal;
+        v_tangent = tangent;
         gl_Position = vec4(position.x, -position.y, position.z, 1.0);
     }
 }
\ No newline at end of file
@@ -0,0 +1,13 @@
+#version 450
+
+// Depth only pass from the light. Uses the same vertex and instance buffers as the main pass.
+layout(location = 0) in vec3 position;
+layout (location = 2) in mat4 model;
+
+layout(push_constant) uniform PushConstants {
+    mat4 light_space;
+} push;
+
+void main() {
+    gl_Position = push.light_space * model * vec4(position, 1.0);
+}
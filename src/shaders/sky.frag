@@ -0,0 +1,10 @@
+#version 450
+
+layout(location = 0) in vec3 v_direction;
+layout(location = 0) out vec4 f_colour;
+
+layout(set = 0, binding = 0) uniform samplerCube sky;
+
+void main() {
+    f_colour = vec4(texture(sky, normalize(v_direction)).rgb, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 450
+
+// One triangle that covers the whole screen, at the far plane so that everything else is in front
+// of it.
+layout(location = 0) out vec3 v_direction;
+
+layout(push_constant) uniform PushConstants {
+    // clip space to world space, without the position of the camera
+    mat4 inverse_view_projection;
+} push;
+
+void main() {
+    vec2 corner = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    gl_Position = vec4(corner, 1.0, 1.0);
+    // Every corner ends up equally far away, so the directions can be interpolated.
+    vec4 far = push.inverse_view_projection * vec4(corner, 1.0, 1.0);
+    v_direction = far.xyz / far.w;
+}
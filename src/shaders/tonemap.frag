@@ -0,0 +1,106 @@
+#version 450
+
+// Turns the HDR colour of the scene into the image: scaled by the exposure, then squeezed into 0 to
+// 1 by the tonemapper. Before the tonemapping level the colour is only clipped, like it was when
+// shader.frag wrote straight into the image. Everything up to here is linear, the sRGB encoding the
+// screen expects comes last.
+layout(location = 0) out vec4 f_colour;
+
+layout(set = 0, binding = 0) uniform sampler2D hdr;
+
+// Written by exposure.comp.
+layout(set = 0, binding = 1) readonly buffer AverageLuminance {
+    float average_luminance;
+};
+
+// The tonemappers, the same as in render.rs.
+const uint CLIP = 0u;
+const uint ACES = 1u;
+const uint REINHARD = 2u;
+const uint FILMIC = 3u;
+
+// Auto exposure brings the average luminance to middle grey.
+const float MIDDLE_GREY = 0.18;
+// Where the filmic curve reaches white.
+const float FILMIC_WHITE = 11.2;
+
+layout(push_constant) uniform PushConstants {
+    // multiplies the colour, on top of the auto exposure
+    float exposure;
+    uint tonemapper;
+    bool auto_exposure;
+    // the image stores what it is given, so the sRGB encoding is left to this shader
+    bool encode_srgb;
+    // leaves the encoding out on the left half of the screen, the way linear colour looks when it
+    // is sent to the screen as it is
+    bool gamma_comparison;
+} push;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve.
+vec3 aces(vec3 x) {
+    return (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+}
+
+vec3 reinhard(vec3 x) {
+    return x / (1.0 + x);
+}
+
+// John Hable's curve from Uncharted 2, before it is scaled to the white point.
+vec3 hable(vec3 x) {
+    const float A = 0.15;
+    const float B = 0.50;
+    const float C = 0.10;
+    const float D = 0.20;
+    const float E = 0.02;
+    const float F = 0.30;
+    return (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F;
+}
+
+vec3 filmic(vec3 x) {
+    return hable(2.0 * x) / hable(vec3(FILMIC_WHITE));
+}
+
+vec3 linear_to_srgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+vec3 srgb_to_linear(vec3 srgb) {
+    vec3 low = srgb / 12.92;
+    vec3 high = pow((srgb + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, lessThanEqual(srgb, vec3(0.04045)));
+}
+
+void main() {
+    vec3 colour = texelFetch(hdr, ivec2(gl_FragCoord.xy), 0).rgb * push.exposure;
+    if (push.auto_exposure) {
+        colour *= MIDDLE_GREY / max(average_luminance, 1e-4);
+    }
+    switch (push.tonemapper) {
+        case ACES:
+            colour = aces(colour);
+            break;
+        case REINHARD:
+            colour = reinhard(colour);
+            break;
+        case FILMIC:
+            colour = filmic(colour);
+            break;
+    }
+    colour = clamp(colour, 0.0, 1.0);
+
+    int middle = textureSize(hdr, 0).x / 2;
+    bool incorrect = push.gamma_comparison && int(gl_FragCoord.x) < middle;
+    if (push.gamma_comparison && int(gl_FragCoord.x) == middle) {
+        colour = vec3(1.0);
+    }
+    // An sRGB image encodes what it is given, so for the incorrect half the encoding is undone in
+    // advance.
+    if (push.encode_srgb && !incorrect) {
+        colour = linear_to_srgb(colour);
+    } else if (!push.encode_srgb && incorrect) {
+        colour = srgb_to_linear(colour);
+    }
+    f_colour = vec4(colour, 1.0);
+}
@@ -0,0 +1,59 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the files are looked at. Polling the modification time is cheap and also catches
+/// editors that save by replacing the file.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Notices when any file out of a set is written, created or deleted.
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut watcher = Self {
+            files: vec![],
+            last_poll: Instant::now(),
+        };
+        watcher.watch(paths);
+        watcher
+    }
+
+    /// Replaces the watched files. Their current state counts as unchanged.
+    pub fn watch(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        self.files = paths
+            .into_iter()
+            .map(|path| {
+                let modified = modified(&path);
+                (path, modified)
+            })
+            .collect();
+    }
+
+    /// Returns true once for every batch of changes. Only looks at the disk every
+    /// `POLL_INTERVAL`, so it can be called every frame.
+    pub fn changed(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = false;
+        for (path, last_modified) in &mut self.files {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// When the file was last written, or `None` if it isn't there.
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,313 @@
+//! Golden-image regression tests.
+//!
+//! Every level is rendered through `--headless` and compared against the reference PNG in
+//! `tests/golden/`. A missing reference fails the test, `UPDATE_GOLDEN=1` records every reference
+//! from the current render instead of comparing. Check new references in.
+//!
+//! The references have not been recorded yet, so the level tests are ignored for now. Record them
+//! on the CI software rasteriser with `UPDATE_GOLDEN=1 cargo test --test golden -- --ignored`,
+//! check in `tests/golden/` and drop the `#[ignore]`s.
+//!
+//! Tolerances can be tuned from the environment:
+//! - `GOLDEN_CHANNEL_TOLERANCE`: how far a single channel may drift before the pixel counts as
+//!   different (0-255, default 3).
+//! - `GOLDEN_MAX_DIFFERING`: fraction of pixels allowed to differ (default 0.005).
+//! - `GOLDEN_MAX_DELTA_E`: maximum mean CIE76 colour difference over the image (default 0.5).
+//!
+//! On failure the actual render and a diff heatmap are written next to each other in the cargo
+//! test temp directory, and their paths are part of the panic message.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SIZE: [u32; 2] = [320, 180];
+
+struct Image {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl Image {
+    fn read(path: &Path) -> Image {
+        let file = BufReader::new(File::open(path).unwrap());
+        let mut reader = png::Decoder::new(file).read_info().unwrap();
+        let mut rgba = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut rgba).unwrap();
+        assert_eq!(
+            (info.color_type, info.bit_depth),
+            (png::ColorType::Rgba, png::BitDepth::Eight),
+            "{} is not an 8 bit RGBA png",
+            path.display()
+        );
+        rgba.truncate(info.buffer_size());
+        Image {
+            width: info.width,
+            height: info.height,
+            rgba,
+        }
+    }
+
+    fn write(&self, path: &Path) {
+        let file = BufWriter::new(File::create(path).unwrap());
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&self.rgba).unwrap();
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = &[u8]> {
+        self.rgba.chunks_exact(4)
+    }
+}
+
+struct Tolerance {
+    channel: u8,
+    max_differing: f64,
+    max_delta_e: f64,
+}
+
+impl Tolerance {
+    fn from_env() -> Tolerance {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            match env::var(name) {
+                Ok(value) => value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{} has an invalid value {:?}", name, value)),
+                Err(_) => default,
+            }
+        }
+
+        Tolerance {
+            channel: var("GOLDEN_CHANNEL_TOLERANCE", 3),
+            max_differing: var("GOLDEN_MAX_DIFFERING", 0.005),
+            max_delta_e: var("GOLDEN_MAX_DELTA_E", 0.5),
+        }
+    }
+}
+
+struct Comparison {
+    differing: f64,
+    mean_delta_e: f64,
+    heatmap: Image,
+}
+
+fn compare(expected: &Image, actual: &Image, tolerance: &Tolerance) -> Comparison {
+    let mut differing = 0;
+    let mut total_delta_e = 0.0;
+    let mut heatmap = Vec::with_capacity(actual.rgba.len());
+
+    for (e, a) in expected.pixels().zip(actual.pixels()) {
+        if e.iter()
+            .zip(a)
+            .any(|(e, a)| e.abs_diff(*a) > tolerance.channel)
+        {
+            differing += 1;
+        }
+
+        let delta_e = delta_e(e, a);
+        total_delta_e += delta_e;
+        heatmap.extend_from_slice(&heat(delta_e));
+    }
+
+    let pixel_count = (actual.width * actual.height) as f64;
+    Comparison {
+        differing: differing as f64 / pixel_count,
+        mean_delta_e: total_delta_e / pixel_count,
+        heatmap: Image {
+            width: actual.width,
+            height: actual.height,
+            rgba: heatmap,
+        },
+    }
+}
+
+/// CIE76 distance between two sRGB pixels. A difference of about 2.3 is just noticeable.
+fn delta_e(a: &[u8], b: &[u8]) -> f64 {
+    let [l1, a1, b1] = lab(a);
+    let [l2, a2, b2] = lab(b);
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+fn lab(pixel: &[u8]) -> [f64; 3] {
+    let linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(pixel[0]), linear(pixel[1]), linear(pixel[2]));
+
+    // D65 white point.
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f64| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Black for identical pixels, through blue and red to yellow at a difference of 20 and above.
+fn heat(delta_e: f64) -> [u8; 4] {
+    let t = (delta_e / 20.0).clamp(0.0, 1.0);
+    let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0) as u8;
+    [
+        channel(t * 3.0 - 1.0),
+        channel(t * 3.0 - 2.0),
+        channel(if t < 1.0 / 3.0 { t * 3.0 } else { 2.0 - t * 3.0 }),
+        255,
+    ]
+}
+
+fn render(level: u32, output: &Path) -> PathBuf {
+    let status = Command::new(env!("CARGO_BIN_EXE_rendering_demo"))
+        .arg("--headless")
+        .args(["--level", &level.to_string()])
+        .args(["--size", &format!("{}x{}", SIZE[0], SIZE[1])])
+        .arg("--output")
+        .arg(output)
+        .status()
+        .unwrap();
+    assert!(status.success(), "headless render of level {} failed", level);
+
+    output.join(format!("level_{}.png", level))
+}
+
+fn check_level(level: u32) {
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let actual_path = render(level, &out_dir.join("actual"));
+    let actual = Image::read(&actual_path);
+
+    let reference_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("level_{}.png", level));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        actual.write(&reference_path);
+        eprintln!("recorded reference {}", reference_path.display());
+        return;
+    }
+    assert!(
+        reference_path.exists(),
+        "no reference {}, record it with UPDATE_GOLDEN=1 and check it in",
+        reference_path.display()
+    );
+    let expected = Image::read(&reference_path);
+    assert_eq!(
+        (expected.width, expected.height),
+        (actual.width, actual.height),
+        "level {} was rendered at a different size than its reference",
+        level
+    );
+
+    let tolerance = Tolerance::from_env();
+    let comparison = compare(&expected, &actual, &tolerance);
+    if comparison.differing > tolerance.max_differing
+        || comparison.mean_delta_e > tolerance.max_delta_e
+    {
+        let failures = out_dir.join("failures");
+        fs::create_dir_all(&failures).unwrap();
+        let failed_actual = failures.join(format!("level_{}_actual.png", level));
+        let failed_diff = failures.join(format!("level_{}_diff.png", level));
+        actual.write(&failed_actual);
+        comparison.heatmap.write(&failed_diff);
+
+        panic!(
+            "level {} differs from {}: {:.3}% of pixels differ (max {:.3}%), mean delta E {:.3} \
+             (max {:.3})\nactual: {}\ndiff: {}",
+            level,
+            reference_path.display(),
+            comparison.differing * 100.0,
+            tolerance.max_differing * 100.0,
+            comparison.mean_delta_e,
+            tolerance.max_delta_e,
+            failed_actual.display(),
+            failed_diff.display(),
+        );
+    }
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_1() {
+    check_level(1);
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_2() {
+    check_level(2);
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_3() {
+    check_level(3);
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_4() {
+    check_level(4);
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_5() {
+    check_level(5);
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_6() {
+    check_level(6);
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_7() {
+    check_level(7);
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_8() {
+    check_level(8);
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_9() {
+    check_level(9);
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_10() {
+    check_level(10);
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_11() {
+    check_level(11);
+}
+
+#[test]
+#[ignore = "no reference images recorded yet"]
+fn level_12() {
+    check_level(12);
+}